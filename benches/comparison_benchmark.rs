@@ -0,0 +1,79 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use felics::compression::CompressDecompress;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageEncoder};
+use std::fs;
+use std::path::Path;
+
+/// The folders of the test suite whose images are compared.
+const FOLDERS: [&str; 3] = ["grayscale/8bit", "grayscale/16bit", "rgb/8bit"];
+
+fn compress_felics(image: &DynamicImage) -> Vec<u8> {
+    let mut sink = Vec::new();
+    match image {
+        DynamicImage::ImageLuma8(image) => image.compress(&mut sink),
+        DynamicImage::ImageLuma16(image) => image.compress(&mut sink),
+        DynamicImage::ImageRgb8(image) => image.compress(&mut sink),
+        DynamicImage::ImageRgb16(image) => image.compress(&mut sink),
+        _ => panic!("Unknown format!"),
+    }
+    .unwrap();
+    sink
+}
+
+fn compress_png(image: &DynamicImage) -> Vec<u8> {
+    let mut sink = Vec::new();
+    let encoder =
+        PngEncoder::new_with_quality(&mut sink, CompressionType::Best, FilterType::Adaptive);
+    encoder
+        .write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().into(),
+        )
+        .unwrap();
+    sink
+}
+
+/// Compresses the images of the test suite using felics and lossless PNG.
+/// The compressed sizes are printed side by side, and criterion measures
+/// the speeds.
+fn comparison(c: &mut Criterion) {
+    let suite = Path::new(env!("CARGO_MANIFEST_DIR")).join("image-suite");
+    let (mut total_felics, mut total_png) = (0, 0);
+
+    for folder in FOLDERS {
+        for file in fs::read_dir(suite.join(folder)).unwrap() {
+            let path = file.unwrap().path();
+            let name = path.strip_prefix(&suite).unwrap().display().to_string();
+            let image = image::open(&path).unwrap();
+
+            let felics_size = compress_felics(&image).len();
+            let png_size = compress_png(&image).len();
+            total_felics += felics_size;
+            total_png += png_size;
+            println!(
+                "{} - Raw size: {}, Felics size: {}, PNG size: {}",
+                name,
+                image.as_bytes().len(),
+                felics_size,
+                png_size
+            );
+
+            let mut group = c.benchmark_group(name);
+            group.throughput(Throughput::Bytes(image.as_bytes().len() as u64));
+            group.sample_size(10);
+            group.bench_function("felics", |b| b.iter(|| compress_felics(black_box(&image))));
+            group.bench_function("png", |b| b.iter(|| compress_png(black_box(&image))));
+            group.finish();
+        }
+    }
+    println!(
+        "Total - Felics size: {}, PNG size: {}",
+        total_felics, total_png
+    );
+}
+
+criterion_group!(benches, comparison);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use felics::compression::{Intensity, KEstimator};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// The number of updates in each iteration of the benchmarks.
+const UPDATES: usize = 1_000_000;
+
+/// Returns the `(context, encoded)` pairs fed to the estimator. The encoded
+/// values are smaller in smaller contexts, like the values of an image.
+fn updates<T>() -> Vec<(u32, u32)>
+where
+    T: Intensity,
+{
+    let mut rng = StdRng::seed_from_u64(0xFE11C5);
+    (0..UPDATES)
+        .map(|_| {
+            let context = rng.gen_range(0..=T::MAX_CONTEXT);
+            let encoded = rng.gen_range(0..=context / 4);
+            (context, encoded)
+        })
+        .collect()
+}
+
+fn bench_update<T>(c: &mut Criterion, name: &str)
+where
+    T: Intensity,
+{
+    let updates = updates::<T>();
+    let mut group = c.benchmark_group("estimator_update");
+    group.throughput(Throughput::Elements(UPDATES as u64));
+    group.sample_size(20);
+
+    group.bench_function(name, |b| {
+        b.iter_batched_ref(
+            KEstimator::default_for::<T>,
+            |estimator| {
+                for &(context, encoded) in &updates {
+                    estimator.update(black_box(context), black_box(encoded));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn estimator_update(c: &mut Criterion) {
+    bench_update::<u8>(c, "u8");
+    bench_update::<u16>(c, "u16");
+}
+
+criterion_group!(benches, estimator_update);
+criterion_main!(benches);
@@ -0,0 +1,135 @@
+//! A derive macro for the `felics::compression::Intensity` trait.
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Error, Expr, ExprArray, ExprLit, Lit, LitInt};
+
+/// The parameters of an `Intensity` implementation, read from the
+/// `#[intensity(...)]` attribute.
+struct IntensityParameters {
+    k_values: Vec<u8>,
+    max_context: u32,
+    count_scaling: Option<u32>,
+    pixel_depth: u8,
+}
+
+/// Derives the `Intensity` trait using the parameters given by the
+/// `#[intensity(...)]` attribute:
+///
+/// - `k_values = [0, 1, 2, 3]`: the reasonable k values, must not be empty.
+/// - `max_context = 1023`: the maximum context, must fit in a `u32`.
+/// - `count_scaling = 512`: the periodic count scaling threshold, optional.
+/// - `pixel_depth = 16`: the pixel depth in the felics header, 8 or 16.
+///   Defaults to 16.
+///
+/// The type must still implement the supertraits of `Intensity`.
+#[proc_macro_derive(Intensity, attributes(intensity))]
+pub fn derive_intensity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let parameters = parse_parameters(&input)?;
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let k_values = &parameters.k_values;
+    let max_context = parameters.max_context;
+    let count_scaling = match parameters.count_scaling {
+        Some(threshold) => quote!(::core::option::Option::Some(#threshold)),
+        None => quote!(::core::option::Option::None),
+    };
+    let pixel_depth = match parameters.pixel_depth {
+        8 => quote!(::felics::compression::PixelDepth::Eight),
+        _ => quote!(::felics::compression::PixelDepth::Sixteen),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::felics::compression::Intensity for #name #type_generics #where_clause {
+            const K_VALUES: &'static [u8] = &[#(#k_values),*];
+            const MAX_CONTEXT: u32 = #max_context;
+            const COUNT_SCALING: ::core::option::Option<u32> = #count_scaling;
+            const PIXEL_DEPTH: ::felics::compression::PixelDepth = #pixel_depth;
+        }
+    })
+}
+
+fn parse_parameters(input: &DeriveInput) -> Result<IntensityParameters, Error> {
+    let mut k_values = None;
+    let mut max_context = None;
+    let mut count_scaling = None;
+    let mut pixel_depth = 16;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("intensity"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("k_values") {
+                let array: ExprArray = meta.value()?.parse()?;
+                let values = array
+                    .elems
+                    .iter()
+                    .map(parse_k_value)
+                    .collect::<Result<Vec<u8>, Error>>()?;
+                if values.is_empty() {
+                    return Err(Error::new_spanned(array, "k_values must not be empty"));
+                }
+                k_values = Some(values);
+            } else if meta.path.is_ident("max_context") {
+                let lit: LitInt = meta.value()?.parse()?;
+                let value = lit.base10_parse::<u64>()?;
+                let value = u32::try_from(value)
+                    .map_err(|_| Error::new_spanned(&lit, "max_context must fit in a u32"))?;
+                max_context = Some(value);
+            } else if meta.path.is_ident("count_scaling") {
+                let lit: LitInt = meta.value()?.parse()?;
+                count_scaling = Some(lit.base10_parse::<u32>()?);
+            } else if meta.path.is_ident("pixel_depth") {
+                let lit: LitInt = meta.value()?.parse()?;
+                pixel_depth = lit.base10_parse::<u8>()?;
+                if pixel_depth != 8 && pixel_depth != 16 {
+                    return Err(Error::new_spanned(lit, "pixel_depth must be 8 or 16"));
+                }
+            } else {
+                return Err(meta.error("unknown intensity parameter"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let missing = |parameter: &str| {
+        Error::new(
+            Span::call_site(),
+            format!("missing `{}` in #[intensity(...)]", parameter),
+        )
+    };
+
+    Ok(IntensityParameters {
+        k_values: k_values.ok_or_else(|| missing("k_values"))?,
+        max_context: max_context.ok_or_else(|| missing("max_context"))?,
+        count_scaling,
+        pixel_depth,
+    })
+}
+
+/// Parses a k value, which must be a valid rice coding parameter.
+fn parse_k_value(expr: &Expr) -> Result<u8, Error> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => {
+            let k = lit.base10_parse::<u8>()?;
+            if k >= 32 {
+                return Err(Error::new_spanned(lit, "k values must be smaller than 32"));
+            }
+            Ok(k)
+        }
+        _ => Err(Error::new_spanned(expr, "expected an integer k value")),
+    }
+}
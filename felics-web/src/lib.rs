@@ -0,0 +1,66 @@
+//! WebAssembly bindings for compressing images with felics in the browser.
+//!
+//! Build with `wasm-pack build --target web` and test with
+//! `wasm-pack test --node`.
+use felics::compression::{compress_dynamic_image, decompress_image};
+use image::ImageFormat;
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+/// Compresses an image file (PNG, TIFF, ...) to a felics file.
+pub fn compress_bytes(data: &[u8]) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(data).map_err(|err| err.to_string())?;
+    let mut compressed = Vec::new();
+    compress_dynamic_image(&mut compressed, &image).map_err(|err| err.to_string())?;
+    Ok(compressed)
+}
+
+/// Decompresses a felics file to a PNG file, which browsers can display.
+pub fn decompress_bytes(data: &[u8]) -> Result<Vec<u8>, String> {
+    let image = decompress_image(data).map_err(|err| err.to_string())?;
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|err| err.to_string())?;
+    Ok(png)
+}
+
+/// Compresses an image file (PNG, TIFF, ...) to a felics file.
+#[wasm_bindgen]
+pub fn compress_image_bytes(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    compress_bytes(data).map_err(|err| JsError::new(&err))
+}
+
+/// Decompresses a felics file to a PNG file.
+#[wasm_bindgen]
+pub fn decompress_image_bytes(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    decompress_bytes(data).map_err(|err| JsError::new(&err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compress_bytes, decompress_bytes};
+    use image::{ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        let image = RgbImage::from_fn(17, 9, |x, y| image::Rgb([x as u8, y as u8, (x * y) as u8]));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let compressed = compress_bytes(&png).unwrap();
+        assert_eq!(&compressed[..4], b"FLCS");
+        let decompressed = decompress_bytes(&compressed).unwrap();
+        let decompressed = image::load_from_memory(&decompressed).unwrap();
+        assert_eq!(decompressed.into_rgb8(), image);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(compress_bytes(b"not an image").is_err());
+        assert!(decompress_bytes(b"not a felics file").is_err());
+    }
+}
@@ -0,0 +1,21 @@
+//! Run with `wasm-pack test --node`.
+#![cfg(target_arch = "wasm32")]
+
+use felics_web::{compress_image_bytes, decompress_image_bytes};
+use image::{GrayImage, ImageFormat};
+use std::io::Cursor;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn round_trip() {
+    let image = GrayImage::from_fn(31, 7, |x, y| image::Luma([(x * 8 + y) as u8]));
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+
+    let compressed = compress_image_bytes(&png).unwrap();
+    let decompressed = decompress_image_bytes(&compressed).unwrap();
+    let decompressed = image::load_from_memory(&decompressed).unwrap();
+    assert_eq!(decompressed.into_luma8(), image);
+}
@@ -0,0 +1,59 @@
+#![no_main]
+
+use arbitrary::{Arbitrary, Unstructured};
+use felics::compression::{decompress_image, write_header, EffectiveBitDepth, Header};
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+
+/// The largest width and height of a generated image.
+/// Keeping the dimensions small makes the fuzzer spend its time
+/// decoding channels instead of allocating huge pixel buffers.
+const MAX_DIMENSION: u32 = 64;
+
+/// The valid orders of the RGB channels.
+const CHANNEL_ORDERS: [[u8; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+/// A felics stream with a valid header, followed by random channel data.
+///
+/// The fields that `read_header` validates, such as the channel order and the
+/// effective bit depth, are only generated with valid values, so every input
+/// reaches `decompress_channel`, where the random bits are fed to the
+/// `KEstimator`, the `PhaseInCoder` and the `RiceCoder`. Fields added to the
+/// header with their own validation must be constrained here as well.
+#[derive(Debug)]
+struct ArbitraryFelicsStream {
+    header: Header,
+    data: Vec<u8>,
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryFelicsStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut header: Header = u.arbitrary()?;
+        header.width %= MAX_DIMENSION + 1;
+        header.height %= MAX_DIMENSION + 1;
+        header.channel_order = *u.choose(&CHANNEL_ORDERS)?;
+        // The effective bit depth must be in [2, bits of the pixel depth].
+        header.effective_bit_depth = header
+            .effective_bit_depth
+            .map(|depth| EffectiveBitDepth(2 + depth.0 % (header.pixel_depth.bits() - 1)));
+
+        let data = u.arbitrary()?;
+        Ok(ArbitraryFelicsStream { header, data })
+    }
+}
+
+fuzz_target!(|stream: ArbitraryFelicsStream| {
+    let mut bytes = Vec::new();
+    write_header(stream.header, &mut bytes).unwrap();
+    bytes.extend_from_slice(&stream.data);
+
+    // Decompression may fail, but it must never panic.
+    let _ = decompress_image(Cursor::new(bytes));
+});
@@ -1,6 +1,6 @@
-use clap::Parser;
-use felics::compression::CompressDecompress;
-use image::{self, io::Reader, DynamicImage};
+use clap::{Parser, ValueEnum};
+use felics::compression::{CodingOptions, CompressDecompress, Quality};
+use image::{self, DynamicImage, ImageReader};
 use std::fs::File;
 use std::io::{self, BufWriter};
 use std::path::PathBuf;
@@ -19,21 +19,48 @@ struct Args {
     /// The output felics file.
     #[arg(short, long)]
     output: PathBuf,
+
+    /// The compression preset, stored in the header of the output file.
+    #[arg(short, long, value_enum, default_value_t = QualityArg::Balanced)]
+    quality: QualityArg,
+}
+
+/// The values of the `--quality` flag.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum QualityArg {
+    /// Fewer k values to try, and an uncompressed first row.
+    Fast,
+    /// The default coding options.
+    Balanced,
+    /// More k values to try, codes the first row using deltas, and adapts
+    /// the parameters faster.
+    Best,
+}
+
+impl From<QualityArg> for Quality {
+    fn from(quality: QualityArg) -> Quality {
+        match quality {
+            QualityArg::Fast => Quality::Fast,
+            QualityArg::Balanced => Quality::Balanced,
+            QualityArg::Best => Quality::Best,
+        }
+    }
 }
 
-fn compress_to<T>(image: T, path: PathBuf) -> io::Result<()>
+fn compress_to<T>(image: T, path: PathBuf, options: CodingOptions) -> io::Result<()>
 where
     T: CompressDecompress,
 {
     let file = File::create(path)?;
     let writer = BufWriter::new(file);
-    image.compress(writer)
+    image.compress_with_options(writer, options)
 }
 
 fn main() {
     let args = Args::parse();
+    let quality = Quality::from(args.quality);
 
-    let reader = match Reader::open(args.input) {
+    let reader = match ImageReader::open(args.input) {
         Ok(r) => r,
         Err(e) => {
             println!("Cannot open file: {}", e);
@@ -52,19 +79,31 @@ fn main() {
     let result = match dynamic_image {
         DynamicImage::ImageLuma8(luma8) => {
             println!("Compressing 8-bit grayscale image...");
-            compress_to(luma8, args.output)
+            compress_to(
+                luma8,
+                args.output,
+                CodingOptions::for_quality::<u8>(quality),
+            )
         }
         DynamicImage::ImageLuma16(luma16) => {
             println!("Compressing 16-bit grayscale image...");
-            compress_to(luma16, args.output)
+            compress_to(
+                luma16,
+                args.output,
+                CodingOptions::for_quality::<u16>(quality),
+            )
         }
         DynamicImage::ImageRgb8(rgb8) => {
             println!("Compressing 8-bit rgb image...");
-            compress_to(rgb8, args.output)
+            compress_to(rgb8, args.output, CodingOptions::for_quality::<u8>(quality))
         }
         DynamicImage::ImageRgb16(rgb16) => {
             println!("Compressing 16-bit rgb image...");
-            compress_to(rgb16, args.output)
+            compress_to(
+                rgb16,
+                args.output,
+                CodingOptions::for_quality::<u16>(quality),
+            )
         }
         _ => {
             println!("Unsupported image format: {:?}", dynamic_image.color());
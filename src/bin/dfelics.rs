@@ -35,7 +35,7 @@ fn main() {
 
     let dyn_image = match decompress_image(reader) {
         Err(error) => {
-            println!("Error while decompressing the image: {:?}", error);
+            println!("Error while decompressing the image: {}", error);
             process::exit(1)
         }
         Ok(d) => d,
@@ -0,0 +1,84 @@
+use clap::Parser;
+use felics::compression::{read_header, Header, Quality};
+use std::fs::File;
+use std::path::PathBuf;
+use std::process;
+
+#[derive(Parser, Debug)]
+#[command(about = "Prints the header of a felics file without decompressing it", long_about = None)]
+#[command(version)]
+struct Args {
+    /// The input felics file.
+    input: PathBuf,
+
+    /// Print the header as a json object.
+    #[arg(long)]
+    json: bool,
+}
+
+fn quality_name(quality: &Option<Quality>) -> &'static str {
+    match quality {
+        Some(Quality::Fast) => "fast",
+        Some(Quality::Balanced) => "balanced",
+        Some(Quality::Best) => "best",
+        None => "unknown",
+    }
+}
+
+fn print_text(header: &Header, file_size: u64) {
+    println!("Signature:   valid");
+    println!("Color type:  {}", header.color_type);
+    println!("Pixel depth: {}", header.pixel_depth.bits());
+    println!("Width:       {}", header.width);
+    println!("Height:      {}", header.height);
+    println!("Quality:     {}", quality_name(&header.quality));
+    println!("File size:   {} bytes", file_size);
+}
+
+fn print_json(header: &Header, file_size: u64) {
+    println!(
+        "{{\"signature_valid\": true, \"color_type\": \"{}\", \"pixel_depth\": {}, \
+         \"width\": {}, \"height\": {}, \"quality\": \"{}\", \"file_size\": {}}}",
+        header.color_type,
+        header.pixel_depth.bits(),
+        header.width,
+        header.height,
+        quality_name(&header.quality),
+        file_size
+    );
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut file = match File::open(&args.input) {
+        Err(e) => {
+            eprintln!("Cannot open input file: {}", e);
+            process::exit(1);
+        }
+        Ok(f) => f,
+    };
+
+    let file_size = match file.metadata() {
+        Err(e) => {
+            eprintln!("Cannot read the file metadata: {}", e);
+            process::exit(1);
+        }
+        Ok(metadata) => metadata.len(),
+    };
+
+    // Only the header is read, the pixel data is never touched.
+    let header = match read_header(&mut file) {
+        Err(error) => {
+            eprintln!("Invalid felics header: {}", error);
+            process::exit(1);
+        }
+        Ok(header) => header,
+    };
+
+    if args.json {
+        print_json(&header, file_size);
+    } else {
+        print_text(&header, file_size);
+    }
+}
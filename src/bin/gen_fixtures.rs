@@ -0,0 +1,136 @@
+use clap::Parser;
+use felics::compression::compress_image_counted;
+use image::{DynamicImage, ImageBuffer, Luma};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[derive(Parser, Debug)]
+#[command(about = "Generates synthetic grayscale test images of known patterns", long_about = None)]
+#[command(version)]
+struct Args {
+    /// The folder in which the images are saved.
+    #[arg(short, long, default_value = "image-suite/synthetic")]
+    output: PathBuf,
+
+    /// The width of the images.
+    #[arg(long, default_value_t = 256)]
+    width: u32,
+
+    /// The height of the images.
+    #[arg(long, default_value_t = 256)]
+    height: u32,
+
+    /// The pixel depth of the images: 8 or 16.
+    #[arg(short, long, default_value_t = 8)]
+    depth: u8,
+}
+
+/// A pattern returns the intensity of the pixel at `(x, y)` in an image of the
+/// given size, between 0 and 1.
+type Pattern = fn(u32, u32, u32, u32) -> f64;
+
+fn gradient(x: u32, y: u32, width: u32, height: u32) -> f64 {
+    (x + y) as f64 / (width + height).saturating_sub(2).max(1) as f64
+}
+
+fn checkerboard(x: u32, y: u32, _width: u32, _height: u32) -> f64 {
+    ((x / 8 + y / 8) % 2) as f64
+}
+
+/// Deterministic noise, so that the images are the same on every run.
+fn noise(x: u32, y: u32, width: u32, _height: u32) -> f64 {
+    // A step of the xorshift generator, seeded by the pixel index.
+    let mut state = (y as u64 * width as u64 + x as u64).wrapping_mul(0x9E3779B97F4A7C15) | 1;
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    (state >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn solid(_x: u32, _y: u32, _width: u32, _height: u32) -> f64 {
+    0.5
+}
+
+fn mandelbrot(x: u32, y: u32, width: u32, height: u32) -> f64 {
+    const MAX_ITERATIONS: u32 = 64;
+
+    let cx = -2.5 + 3.5 * x as f64 / width as f64;
+    let cy = -1.0 + 2.0 * y as f64 / height as f64;
+    let (mut zx, mut zy) = (0.0, 0.0);
+
+    let mut iterations = 0;
+    while iterations < MAX_ITERATIONS && zx * zx + zy * zy <= 4.0 {
+        (zx, zy) = (zx * zx - zy * zy + cx, 2.0 * zx * zy + cy);
+        iterations += 1;
+    }
+    iterations as f64 / MAX_ITERATIONS as f64
+}
+
+const PATTERNS: [(&str, Pattern); 5] = [
+    ("gradient", gradient),
+    ("checkerboard", checkerboard),
+    ("noise", noise),
+    ("solid", solid),
+    ("mandelbrot", mandelbrot),
+];
+
+/// Saves the image as a PNG file, and prints its compression ratio, the
+/// compressed size over the raw size, as a line of `tests/synthetic-ratios.txt`.
+fn save_fixture(image: DynamicImage, path: &Path) -> io::Result<()> {
+    image.save(path).map_err(io::Error::other)?;
+
+    let raw_size = image.as_bytes().len();
+    let compressed_size = match image {
+        DynamicImage::ImageLuma8(image) => compress_image_counted(io::sink(), image)?,
+        DynamicImage::ImageLuma16(image) => compress_image_counted(io::sink(), image)?,
+        _ => unreachable!(),
+    };
+    let ratio = compressed_size as f64 / raw_size.max(1) as f64;
+
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    println!("{} {:.6}", file_name, ratio);
+    Ok(())
+}
+
+fn generate(args: &Args) -> io::Result<()> {
+    if args.depth != 8 && args.depth != 16 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The pixel depth must be 8 or 16",
+        ));
+    }
+    fs::create_dir_all(&args.output)?;
+
+    for (name, pattern) in PATTERNS {
+        let file_name = format!(
+            "{}-{}x{}-{}bit.png",
+            name, args.width, args.height, args.depth
+        );
+        let path = args.output.join(file_name);
+        let value = |x, y| pattern(x, y, args.width, args.height).clamp(0.0, 1.0);
+
+        let image = match args.depth {
+            8 => DynamicImage::ImageLuma8(ImageBuffer::from_fn(args.width, args.height, |x, y| {
+                Luma([(value(x, y) * u8::MAX as f64).round() as u8])
+            })),
+            _ => {
+                DynamicImage::ImageLuma16(ImageBuffer::from_fn(args.width, args.height, |x, y| {
+                    Luma([(value(x, y) * u16::MAX as f64).round() as u16])
+                }))
+            }
+        };
+        save_fixture(image, &path)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Err(e) = generate(&args) {
+        println!("Cannot generate the fixtures: {}", e);
+        process::exit(1)
+    }
+}
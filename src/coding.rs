@@ -1,3 +1,4 @@
 pub mod bitwrite_mock;
+pub mod fibonacci_coding;
 pub mod phase_in_coding;
 pub mod rice_coding;
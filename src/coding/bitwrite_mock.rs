@@ -18,6 +18,12 @@ impl BitWriterMock {
     }
 }
 
+impl Default for BitWriterMock {
+    fn default() -> Self {
+        BitWriterMock::new()
+    }
+}
+
 impl BitWrite for BitWriterMock {
     fn write_bit(&mut self, bit: bool) -> io::Result<()> {
         match bit {
@@ -41,7 +47,7 @@ impl BitWrite for BitWriterMock {
     }
 
     fn byte_align(&mut self) -> io::Result<()> {
-        while self.content.len() % 8 != 0 {
+        while !self.content.len().is_multiple_of(8) {
             self.write_bit(false)?;
         }
         Ok(())
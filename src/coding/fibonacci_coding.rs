@@ -0,0 +1,182 @@
+use bitstream_io::{BitRead, BitWrite};
+use std::io;
+
+/// The number of Fibonacci numbers, starting from 1 and 2, that fit in a `u32`.
+const FIBONACCI_COUNT: usize = 46;
+
+/// The Fibonacci numbers used by the codes: `1, 2, 3, 5, 8, ...`
+const FIBONACCI: [u32; FIBONACCI_COUNT] = {
+    let mut fibonacci = [1; FIBONACCI_COUNT];
+    fibonacci[1] = 2;
+    let mut i = 2;
+    while i < FIBONACCI_COUNT {
+        fibonacci[i] = fibonacci[i - 1] + fibonacci[i - 2];
+        i += 1;
+    }
+    fibonacci
+};
+
+/// A struct that is used to encode and decode positive numbers using Fibonacci coding.
+///
+/// The code of a number is its Zeckendorf representation, a sum of non-consecutive
+/// Fibonacci numbers, written from the smallest Fibonacci number to the largest one
+/// and followed by an extra `1`. As the representation never has two consecutive `1`s,
+/// the code always ends with `11`. Small numbers get the shortest codes: `1` is coded as `11`.
+///
+/// For more information, see: [Fibonacci coding](https://en.wikipedia.org/wiki/Fibonacci_coding)
+#[derive(Default)]
+pub struct FibonacciCoder;
+
+impl FibonacciCoder {
+    pub fn new() -> FibonacciCoder {
+        FibonacciCoder
+    }
+
+    /// Returns the index of the largest Fibonacci number that is not bigger than `number`.
+    fn largest_index(number: u32) -> usize {
+        FIBONACCI.partition_point(|&fibonacci| fibonacci <= number) - 1
+    }
+
+    /// Writes the Fibonacci code of the number to the given `BitWrite`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is 0.
+    pub fn encode<T>(&self, bitwrite: &mut T, number: u32) -> io::Result<()>
+    where
+        T: BitWrite,
+    {
+        assert!(number > 0);
+
+        let largest = FibonacciCoder::largest_index(number);
+        let mut bits = [false; FIBONACCI_COUNT];
+        let mut remaining = number;
+
+        // Greedily subtract the largest Fibonacci numbers.
+        for i in (0..=largest).rev() {
+            if FIBONACCI[i] <= remaining {
+                bits[i] = true;
+                remaining -= FIBONACCI[i];
+            }
+        }
+
+        for &bit in &bits[..=largest] {
+            bitwrite.write_bit(bit)?;
+        }
+        bitwrite.write_bit(true)?;
+        Ok(())
+    }
+
+    /// Decodes a Fibonacci coded number by reading from the provided `BitRead`.
+    pub fn decode<T>(&self, bitread: &mut T) -> io::Result<u32>
+    where
+        T: BitRead,
+    {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid Fibonacci code");
+
+        let mut number: u32 = 0;
+        let mut last_bit = false;
+        let mut i = 0;
+
+        loop {
+            let bit = bitread.read_bit()?;
+            if bit && last_bit {
+                return Ok(number);
+            }
+            if bit {
+                let fibonacci = *FIBONACCI.get(i).ok_or_else(invalid)?;
+                number = number.checked_add(fibonacci).ok_or_else(invalid)?;
+            }
+            last_bit = bit;
+            i += 1;
+        }
+    }
+
+    /// Returns the length of the Fibonacci code of the given number,
+    /// without encoding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is 0.
+    pub fn code_length(&self, number: u32) -> u32 {
+        assert!(number > 0);
+        FibonacciCoder::largest_index(number) as u32 + 2
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coding::bitwrite_mock::BitWriterMock;
+    use bitstream_io::{BigEndian, BitCounter, BitReader, BitWriter};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_fibonacci_table() {
+        assert_eq!(FIBONACCI[..8], [1, 2, 3, 5, 8, 13, 21, 34]);
+        assert_eq!(FIBONACCI[FIBONACCI_COUNT - 1], 2971215073);
+        assert!(FIBONACCI[FIBONACCI_COUNT - 1]
+            .checked_add(FIBONACCI[FIBONACCI_COUNT - 2])
+            .is_none());
+    }
+
+    #[test]
+    fn test_fibonacci_encoding() {
+        let coder = FibonacciCoder::new();
+        for (number, code) in [
+            (1, "11"),
+            (2, "011"),
+            (3, "0011"),
+            (4, "1011"),
+            (11, "001011"),
+        ] {
+            let mut bitwriter = BitWriterMock::new();
+            coder.encode(&mut bitwriter, number).unwrap();
+            assert_eq!(bitwriter.content(), code);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_fibonacci_zero() {
+        let mut bitwriter = BitWriterMock::new();
+        FibonacciCoder::new().encode(&mut bitwriter, 0).unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_decoding() {
+        let coder = FibonacciCoder::new();
+        let numbers: Vec<u32> = (1..=1000).chain([u32::MAX - 1, u32::MAX]).collect();
+
+        let mut to = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut to);
+        for &number in &numbers {
+            coder.encode(&mut bitwriter, number).unwrap();
+        }
+        bitwriter.byte_align().unwrap();
+
+        let mut from = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+        for &number in &numbers {
+            assert_eq!(coder.decode(&mut from).unwrap(), number);
+        }
+    }
+
+    #[test]
+    fn test_fibonacci_invalid_code() {
+        // Too many bits before the terminating `11`.
+        let to = [0b1010_1010; 8];
+        let mut from = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+        let err = FibonacciCoder::new().decode(&mut from).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_fibonacci_code_length() {
+        let coder = FibonacciCoder::new();
+        for number in (1..=1000).chain([u32::MAX]) {
+            let mut bitcounter = BitCounter::<u32, BigEndian>::new();
+            coder.encode(&mut bitcounter, number).unwrap();
+            assert_eq!(bitcounter.written(), coder.code_length(number));
+        }
+    }
+}
@@ -21,18 +21,55 @@ impl PhaseInCoder {
     ///
     /// Panics if `n` is 0 or greater or equal to 2^31.
     pub fn new(n: u32) -> PhaseInCoder {
-        let m = n.checked_ilog2().expect("n is 0!");
+        PhaseInCoder::new_checked(n).expect("n is 0 or too big!")
+    }
+
+    /// Constructs a phase-in coder for the given range: `[0, n-1]`.
+    ///
+    /// Returns `None` if `n` is 0 or greater or equal to 2^31.
+    pub fn new_checked(n: u32) -> Option<PhaseInCoder> {
+        let m = n.checked_ilog2()?;
 
         // Compute neighbouring powers of two.
         let lpw: u32 = 1 << m;
-        let rpw: u32 = 1u32.checked_shl(m + 1).expect("n is too big!");
+        let rpw: u32 = 1u32.checked_shl(m + 1)?;
 
-        PhaseInCoder {
+        Some(PhaseInCoder {
             n,
             m,
             left_p: n - lpw,
             right_p: rpw - n,
-        }
+        })
+    }
+
+    /// Constructs a phase-in coder for the range `[0, 2^m - 1]`. The codes are then
+    /// the `m`-bit binary representations of the numbers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `m` is greater or equal to 31.
+    pub fn new_power_of_two(m: u32) -> PhaseInCoder {
+        let n = 1u32.checked_shl(m).expect("m is too big!");
+        PhaseInCoder::new(n)
+    }
+
+    /// Constructs a phase-in coder for the signed range: `[-range, range]`,
+    /// to be used with `encode_signed` and `decode_signed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is greater or equal to 2^30.
+    pub fn new_signed(range: u32) -> PhaseInCoder {
+        let n = range
+            .checked_mul(2)
+            .and_then(|n| n.checked_add(1))
+            .expect("range is too big!");
+        PhaseInCoder::new(n)
+    }
+
+    /// The offset that maps signed numbers to the `[0, n-1]` range.
+    fn signed_offset(&self) -> i64 {
+        (self.n / 2) as i64
     }
 
     /// Rotates all numbers in the domain `[0, n-1] to the right p positions.
@@ -62,6 +99,11 @@ impl PhaseInCoder {
     {
         assert!(number < self.n);
 
+        // If n is a power of two, all codewords have m bits and no rotation is needed.
+        if self.left_p == 0 {
+            return bitwrite.write(self.m, number);
+        }
+
         let number = self.rotate_right(number);
 
         // The first P integers: [0, P - 1] receive short codewords (m bits).
@@ -78,11 +120,55 @@ impl PhaseInCoder {
             let to_encode = pair + self.right_p;
 
             bitwrite.write(self.m, to_encode)?;
-            bitwrite.write_bit(if last_bit == 1 { true } else { false })?;
+            bitwrite.write_bit(last_bit == 1)?;
         }
         Ok(())
     }
 
+    /// Writes the phase-in coding of a signed number in the range
+    /// `[-n/2, n-1-n/2]` to the given `BitWrite`.
+    ///
+    /// The numbers are offset by `n/2`, so that the numbers around 0 end up
+    /// in the middle of the range, where the codewords are the shortest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is out of range.
+    pub fn encode_signed<T>(&self, bitwrite: &mut T, number: i32) -> io::Result<()>
+    where
+        T: BitWrite,
+    {
+        let offset = number as i64 + self.signed_offset();
+        assert!(offset >= 0 && offset < self.n as i64);
+        self.encode(bitwrite, offset as u32)
+    }
+
+    /// Decodes the phase-in coding of a signed number that was written
+    /// using `encode_signed`.
+    pub fn decode_signed<T>(&self, bitread: &mut T) -> io::Result<i32>
+    where
+        T: BitRead,
+    {
+        let number = self.decode(bitread)? as i64 - self.signed_offset();
+        Ok(number as i32)
+    }
+
+    /// Returns the length of the phase-in code of the given number, without
+    /// encoding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is out of range.
+    pub fn code_length(&self, number: u32) -> u32 {
+        assert!(number < self.n);
+
+        if self.rotate_right(number) < self.right_p {
+            self.m
+        } else {
+            self.m + 1
+        }
+    }
+
     /// Decodes the phase-in coding of a number in the range `[0, n-1]` by reading from the
     /// provided `BitRead`.
     ///
@@ -94,6 +180,10 @@ impl PhaseInCoder {
         // Read m bits.
         let first_m = bitread.read(self.m)?;
 
+        if self.left_p == 0 {
+            return Ok(first_m);
+        }
+
         if first_m < self.right_p {
             return Ok(self.rotate_left(first_m));
         }
@@ -116,7 +206,7 @@ impl PhaseInCoder {
 mod test {
     use super::PhaseInCoder;
     use crate::coding::bitwrite_mock::BitWriterMock;
-    use bitstream_io::{BigEndian, BitReader, BitWrite, BitWriter};
+    use bitstream_io::{BigEndian, BitRead, BitReader, BitRecorder, BitWrite, BitWriter};
     use rand::seq::SliceRandom;
     use std::io::Cursor;
 
@@ -132,6 +222,16 @@ mod test {
         PhaseInCoder::new(1 << 31);
     }
 
+    #[test]
+    fn test_new_checked() {
+        assert!(PhaseInCoder::new_checked(0).is_none());
+        assert!(PhaseInCoder::new_checked(1 << 31).is_none());
+        assert!(PhaseInCoder::new_checked(u32::MAX).is_none());
+
+        let coder = PhaseInCoder::new_checked((1 << 31) - 1).unwrap();
+        assert_eq!(coder.m, 30);
+    }
+
     // Taken from the dummy chapter in the phase-in coding article.
     #[test]
     fn test_new_coder() {
@@ -169,6 +269,121 @@ mod test {
         coder.encode(&mut bitwriter, 15).unwrap();
     }
 
+    #[test]
+    fn test_power_of_two() {
+        for m in [0, 3, 4, 10] {
+            let coder = PhaseInCoder::new_power_of_two(m);
+            assert_eq!(coder.n, 1 << m);
+            assert_eq!(coder.left_p, 0);
+
+            for number in 0..coder.n {
+                let mut phase_in = BitWriterMock::new();
+                coder.encode(&mut phase_in, number).unwrap();
+                let mut binary = BitWriterMock::new();
+                binary.write(m, number).unwrap();
+                assert_eq!(phase_in.content(), binary.content());
+            }
+        }
+    }
+
+    // With a single symbol, no bits are needed.
+    #[test]
+    fn test_single_symbol() {
+        let coder = PhaseInCoder::new(1);
+        assert_eq!(coder.code_length(0), 0);
+
+        let mut recorder = BitRecorder::<u32, BigEndian>::new();
+        coder.encode(&mut recorder, 0).unwrap();
+        assert_eq!(recorder.written(), 0);
+
+        let mut to = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut to);
+        coder.encode(&mut bitwriter, 0).unwrap();
+        bitwriter.write_bit(true).unwrap();
+        coder.encode(&mut bitwriter, 0).unwrap();
+        bitwriter.write_bit(false).unwrap();
+        bitwriter.byte_align().unwrap();
+
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+        assert_eq!(coder.decode(&mut bitreader).unwrap(), 0);
+        assert!(bitreader.read_bit().unwrap());
+        assert_eq!(coder.decode(&mut bitreader).unwrap(), 0);
+        assert!(!bitreader.read_bit().unwrap());
+    }
+
+    // With two symbols, both codewords are a single bit.
+    #[test]
+    fn test_two_symbols() {
+        let coder = PhaseInCoder::new(2);
+        assert_eq!(coder.m, 1);
+        assert_eq!(coder.left_p, 0);
+        assert_eq!(coder.right_p, 2);
+
+        assert_eq!(get_phase_in_codes(2), vec!["0", "1"]);
+        assert_eq!(coder.code_length(0), 1);
+        assert_eq!(coder.code_length(1), 1);
+
+        let mut to = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut to);
+        for number in [1, 0, 0, 1, 1] {
+            coder.encode(&mut bitwriter, number).unwrap();
+        }
+        bitwriter.byte_align().unwrap();
+        assert_eq!(to, [0b1001_1000]);
+
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+        for number in [1, 0, 0, 1, 1] {
+            assert_eq!(coder.decode(&mut bitreader).unwrap(), number);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_power_of_two_too_big() {
+        PhaseInCoder::new_power_of_two(31);
+    }
+
+    #[test]
+    fn test_signed_encoding() {
+        let coder = PhaseInCoder::new_signed(3);
+        let codes: Vec<String> = (-3..=3)
+            .map(|number| {
+                let mut bitwriter = BitWriterMock::new();
+                coder.encode_signed(&mut bitwriter, number).unwrap();
+                bitwriter.content()
+            })
+            .collect();
+        assert_eq!(codes, vec!["011", "110", "111", "00", "100", "101", "010"]);
+    }
+
+    #[test]
+    fn test_signed_decoding() {
+        for range in [0, 1, 2, 7, 100, 1000] {
+            let coder = PhaseInCoder::new_signed(range);
+            let range = range as i32;
+
+            let mut to = Vec::new();
+            let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut to);
+            for number in -range..=range {
+                coder.encode_signed(&mut bitwriter, number).unwrap();
+            }
+            bitwriter.byte_align().unwrap();
+
+            let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+            for number in -range..=range {
+                assert_eq!(coder.decode_signed(&mut bitreader).unwrap(), number);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_signed_value_outside_range() {
+        let coder = PhaseInCoder::new_signed(5);
+        let mut bitwriter = BitWriterMock::new();
+        coder.encode_signed(&mut bitwriter, -6).unwrap();
+    }
+
     // Utility function to compute the phase in codes of the set [0, n-1]
     fn get_phase_in_codes(n: u32) -> Vec<String> {
         let coder = PhaseInCoder::new(n);
@@ -224,6 +439,48 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_code_length() {
+        for n in [1, 7, 8, 9, 15, 16, 17] {
+            let coder = PhaseInCoder::new(n);
+            let codes = get_phase_in_codes(n);
+            for number in 0..n {
+                assert_eq!(
+                    coder.code_length(number),
+                    codes[number as usize].len() as u32
+                );
+            }
+        }
+    }
+
+    // For n = 2^k - 1, only one number gets a short codeword, which stresses
+    // the rotation logic.
+    #[test]
+    fn test_phase_in_powers_of_two_minus_one() {
+        for k in 1..=12 {
+            let n: u32 = (1 << k) - 1;
+            let coder = PhaseInCoder::new(n);
+            assert_eq!(coder.left_p, (1 << (k - 1)) - 1);
+            assert_eq!(coder.right_p, 1);
+
+            let mut to = Vec::new();
+            let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut to);
+            for number in 0..n {
+                let mut recorder = BitRecorder::<u32, BigEndian>::new();
+                coder.encode(&mut recorder, number).unwrap();
+                assert_eq!(recorder.written(), coder.code_length(number));
+
+                coder.encode(&mut bitwriter, number).unwrap();
+            }
+            bitwriter.byte_align().unwrap();
+
+            let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+            for number in 0..n {
+                assert_eq!(coder.decode(&mut bitreader).unwrap(), number);
+            }
+        }
+    }
+
     // Enumerate possible values for n. For each domain `[0, n-1]`, shuffle the values in the domain
     // and encode them using phase-in coding. Then, decode them and check if we get the same values.
     #[test]
@@ -17,9 +17,16 @@ impl RiceCoder {
     ///
     /// Panics if k is greater than 31.
     pub fn new(k: u8) -> RiceCoder {
-        let m = 1u32.checked_shl(k as u32).expect("k is too big!");
+        RiceCoder::new_checked(k).expect("k is too big!")
+    }
+
+    /// Creates a new RiceCoder for m = 2^k.
+    ///
+    /// Returns `None` if k is greater than 31.
+    pub fn new_checked(k: u8) -> Option<RiceCoder> {
+        let m = 1u32.checked_shl(k as u32)?;
         let mask_first_k = m - 1;
-        RiceCoder { k, m, mask_first_k }
+        Some(RiceCoder { k, m, mask_first_k })
     }
 
     /// Writes the rice encoded number to the given `BitWrite`.
@@ -87,6 +94,16 @@ mod test {
         let _ = RiceCoder::new(32);
     }
 
+    #[test]
+    fn test_rice_new_checked() {
+        assert!(RiceCoder::new_checked(32).is_none());
+        assert!(RiceCoder::new_checked(u8::MAX).is_none());
+
+        let coder = RiceCoder::new_checked(31).unwrap();
+        assert_eq!(coder.m, 1 << 31);
+        assert_eq!(coder.mask_first_k, (1 << 31) - 1);
+    }
+
     #[test]
     fn test_rice_decoding() {
         let mut to = Vec::new();
@@ -106,6 +123,26 @@ mod test {
         assert_eq!(c.decode(&mut from).unwrap(), 10);
     }
 
+    // With k = 0, the quotient is the number itself, so the codeword is
+    // over a million bits long.
+    #[test]
+    fn test_rice_large_unary() {
+        let coder = RiceCoder::new(0);
+        let number = 1_000_000;
+        assert_eq!(coder.code_length(number), 1_000_001);
+
+        let mut to = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut to);
+        coder.encode(&mut bitwriter, number).unwrap();
+        coder.encode(&mut bitwriter, 3).unwrap();
+        bitwriter.byte_align().unwrap();
+        assert_eq!(to.len(), (1_000_001 + 4usize).div_ceil(8));
+
+        let mut from = BitReader::<_, BigEndian>::new(Cursor::new(&to));
+        assert_eq!(coder.decode(&mut from).unwrap(), number);
+        assert_eq!(coder.decode(&mut from).unwrap(), 3);
+    }
+
     #[test]
     #[ignore]
     fn test_rice_decoding_extensive() {
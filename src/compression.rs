@@ -1,32 +1,72 @@
 use crate::coding::{phase_in_coding::PhaseInCoder, rice_coding::RiceCoder};
-use bitstream_io::{self, BigEndian, BitRead, BitReader, BitWrite, BitWriter};
+use bitstream_io::{self, BigEndian, BitCounter, BitRead, BitReader, BitWrite, BitWriter};
 use color_transform::{rgb_to_ycocg, ycocg_to_rgb};
-pub use error::DecompressionError;
-pub use format::{read_header, write_header, ColorType, Header, PixelDepth};
+pub use error::{CompressIntoSliceError, DecompressionError};
+pub use file::{compress_file, decompress_file};
+#[cfg(feature = "fingerprint")]
+pub use fingerprint::fingerprint_compressed;
+use format::HEADER_SIZE;
+pub use format::{
+    compute_xor_checksum, read_header, verify_xor_checksum, write_header, ColorType, Header,
+    PixelDepth, FORMAT_VERSION, XOR_CHECKSUM_SIZE,
+};
 use image::{DynamicImage, ImageBuffer, Luma, Pixel, Rgb};
-use parameter_selection::KEstimator;
+use misc::RasterScan;
+pub use misc::{nearest_neighbours_with_fallback, NeighborhoodContext};
+pub use options::{
+    ChromaSubsampling, CodingOptions, EffectiveBitDepth, FirstRowMode, OutputBufferMode, Quality,
+};
+pub use parameter_selection::{
+    optimal_k_for_mean, BayesianKEstimator, ContextStatistics, KEstimator, KEstimatorSparse,
+};
+pub use pipeline::{Pipeline, Transform};
+use preprocess::{chroma_dimensions, downsample_chroma, upsample_chroma};
+pub use preprocess::{resize_bilinear, resize_nearest_neighbor};
 use std::cmp;
 use std::io::{self, Read, Write};
+use std::ops::Deref;
 pub use traits::{CompressDecompress, Intensity};
 
+/// Derives `Intensity` for custom pixel types, see the `felics-derive` crate.
+#[cfg(feature = "derive")]
+pub use felics_derive::Intensity;
+
 mod color_transform;
 mod error;
+mod file;
+#[cfg(feature = "fingerprint")]
+mod fingerprint;
 mod format;
 mod misc;
+mod options;
 mod parameter_selection;
+mod pipeline;
+mod preprocess;
 mod traits;
 
 /// The possible intensity of a pixel relative to the context induced by its two
 /// nearest neighbours: `[L, H]`.
 #[derive(PartialEq, Debug)]
-enum PixelIntensity {
+#[allow(clippy::enum_variant_names)]
+pub enum PixelIntensity {
+    /// The pixel is in `[L, H]`, and is coded using a phase-in code.
     InRange,
+    /// The pixel is below `L`, and `L - P - 1` is coded using a rice code.
     BelowRange,
+    /// The pixel is above `H`, and `P - H - 1` is coded using a rice code.
     AboveRange,
 }
 
-/// Writes the `PixelIntensity` to the given `BitWrite` using simple prefix codes.
-fn encode_intensity<T>(bitwrite: &mut T, intensity: PixelIntensity) -> io::Result<()>
+/// Writes the `PixelIntensity` to the given `BitWrite` using simple prefix codes:
+///
+/// | Intensity    | Code |
+/// |--------------|------|
+/// | `InRange`    | `1`  |
+/// | `AboveRange` | `01` |
+/// | `BelowRange` | `00` |
+///
+/// Pixels are in range about half of the time, so they get the shortest code.
+pub fn encode_intensity<T>(bitwrite: &mut T, intensity: PixelIntensity) -> io::Result<()>
 where
     T: BitWrite,
 {
@@ -44,8 +84,9 @@ where
     Ok(())
 }
 
-/// Reads a `PixelIntensity` from the given `BitRead`.
-fn decode_intensity<T>(bitread: &mut T) -> io::Result<PixelIntensity>
+/// Reads a `PixelIntensity` from the given `BitRead`, using the prefix codes
+/// of `encode_intensity`.
+pub fn decode_intensity<T>(bitread: &mut T) -> io::Result<PixelIntensity>
 where
     T: BitRead,
 {
@@ -60,66 +101,317 @@ where
     Ok(PixelIntensity::BelowRange)
 }
 
-#[derive(Copy, Clone)]
-struct CodingOptions {
-    max_context: u32,
-    k_values: &'static [u8],
-    periodic_count_scaling: Option<u32>,
+/// The list of k values used to encode the length of a run of constant pixels.
+const RUN_K_VALUES: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// Creates the estimator used to pick the rice parameter for run lengths.
+/// All runs share the same context.
+fn run_estimator(options: CodingOptions) -> KEstimator {
+    KEstimator::new(0, RUN_K_VALUES, options.periodic_count_scaling)
+}
+
+/// Creates the estimator used to pick the rice parameter for the differences
+/// between the pixels of the first row, when it is coded using `FirstRowMode::Delta`.
+fn first_row_estimator(options: CodingOptions) -> KEstimator {
+    KEstimator::new(0, options.k_values(), options.periodic_count_scaling)
+}
+
+/// Returns the estimator used to pick the rice parameter of the pixels of a
+/// band, creating it on the first call.
+///
+/// Later bands reset the same estimator in place instead of creating a new
+/// one, which holds the code lengths of every context and is large for `u16`
+/// channels, so a channel split into many small bands stays cheap to code.
+fn band_estimator(estimator: &mut Option<KEstimator>, options: CodingOptions) -> &mut KEstimator {
+    if let Some(estimator) = estimator.as_mut() {
+        estimator.reset();
+    }
+    estimator.get_or_insert_with(|| {
+        KEstimator::new(
+            options.max_context(),
+            options.k_values(),
+            options.periodic_count_scaling,
+        )
+    })
+}
+
+/// Writes the length of a run of constant pixels using adaptive rice coding.
+fn encode_run<T>(bitwrite: &mut T, estimator: &mut KEstimator, length: u32) -> io::Result<()>
+where
+    T: BitWrite,
+{
+    RiceCoder::new(estimator.get_k(0)).encode(bitwrite, length)?;
+    estimator.update(0, length);
+    Ok(())
+}
+
+/// Reads the length of a run of constant pixels from the given `BitRead`.
+fn decode_run<T>(bitread: &mut T, estimator: &mut KEstimator) -> Result<u32, DecompressionError>
+where
+    T: BitRead,
+{
+    let length = RiceCoder::new_checked(estimator.get_k(0))
+        .ok_or(DecompressionError::InvalidValue)?
+        .decode(bitread)?;
+    estimator.update(0, length);
+    Ok(length)
+}
+
+/// Returns the number of bits used to store a pixel verbatim, like the two
+/// seed pixels of a channel, or the pixels of a verbatim first row.
+/// The YCoCg channels can be negative, so one extra bit is used for the sign.
+fn verbatim_bits(options: CodingOptions) -> u32 {
+    u32::BITS - options.max_value().leading_zeros() + 1
+}
+
+/// Maps signed differences to unsigned numbers: `0, -1, 1, -2, 2, ...`
+/// become `0, 1, 2, 3, 4, ...`
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Opposite of `zigzag`.
+fn unzigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// Returns the shift of the in-range values of a context, in `[0, context]`,
+/// for the given phase-in rotation bias.
+fn phase_in_shift(context: u32, bias: i32) -> u32 {
+    let n = context as i64 + 1;
+    (bias as i64)
+        .clamp(-(context as i64), context as i64)
+        .rem_euclid(n) as u32
+}
+
+/// Writes a pixel of the first row, when the first row is not coded
+/// using the nearest neighbours.
+fn encode_first_row<T>(
+    bitwrite: &mut T,
+    estimator: &mut KEstimator,
+    options: CodingOptions,
+    pixel: i32,
+    left: i32,
+) -> io::Result<()>
+where
+    T: BitWrite,
+{
+    if options.first_row_mode == FirstRowMode::Verbatim {
+        return bitwrite.write_signed(verbatim_bits(options), pixel);
+    }
+
+    let delta = zigzag(pixel - left);
+    RiceCoder::new(estimator.get_k(0)).encode(bitwrite, delta)?;
+    estimator.update(0, delta);
+    Ok(())
+}
+
+/// Reads a pixel of the first row, when the first row is not coded
+/// using the nearest neighbours.
+fn decode_first_row<T>(
+    bitread: &mut T,
+    estimator: &mut KEstimator,
+    options: CodingOptions,
+    left: i32,
+) -> Result<i32, DecompressionError>
+where
+    T: BitRead,
+{
+    if options.first_row_mode == FirstRowMode::Verbatim {
+        return Ok(bitread.read_signed(verbatim_bits(options))?);
+    }
+
+    let delta = RiceCoder::new_checked(estimator.get_k(0))
+        .ok_or(DecompressionError::InvalidValue)?
+        .decode(bitread)?;
+    estimator.update(0, delta);
+    left.checked_add(unzigzag(delta))
+        .ok_or(DecompressionError::ValueOverflow)
+}
+
+/// Checks that a decoded pixel is in the `[0, max_value]` range, before it is
+/// converted to the pixel intensity type.
+fn check_pixel_value(value: i32, max_value: i32) -> Result<i32, DecompressionError> {
+    if value < 0 || value > max_value {
+        return Err(DecompressionError::InvalidValue);
+    }
+    Ok(value)
+}
+
+/// Returns the number of pixels of a `width x height` channel, or an
+/// `InvalidInput` error if it does not fit in a `u32` or a `usize`, so that it
+/// can still be decompressed.
+fn channel_size(width: u32, height: u32) -> io::Result<usize> {
+    width
+        .checked_mul(height)
+        .and_then(|size| size.try_into().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The image is too large"))
 }
 
 /// Compresses a channel and writes it to the given `BitWrite`.
 ///
+/// The pixels of the channel are consumed in raster-scan order. Because the
+/// nearest neighbours of a pixel are at most two rows behind it, only the last
+/// `2*width` pixels are kept in memory.
+///
+/// # Panics
+///
+/// This functions assumes that the `channel` yields at least `width*height`
+/// pixels. It will panic if the `channel` is not big enough.
+fn compress_channel<I, W>(
+    channel: I,
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+    bitwrite: &mut W,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = i32>,
+    W: BitWrite,
+{
+    compress_band(channel, width, height, options, &mut None, bitwrite)
+}
+
+/// Compresses a band of a channel like `compress_channel`, reusing the
+/// estimator of the previous band, if any. See `band_estimator`.
+fn compress_band<I, W>(
+    channel: I,
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+    estimator: &mut Option<KEstimator>,
+    bitwrite: &mut W,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = i32>,
+    W: BitWrite,
+{
+    channel_size(width, height)?;
+    let mut channel = channel.into_iter();
+    let mut next_pixel = || channel.next().expect("The channel is not big enough!");
+
+    // The seeds of channels with less than two pixels are padded with zeros.
+    let seeds = match (width, height) {
+        (0, _) | (_, 0) => (0, 0),
+        (1, 1) => (next_pixel(), 0),
+        _ => (next_pixel(), next_pixel()),
+    };
+    compress_channel_seeded(channel, width, height, seeds, options, estimator, bitwrite)
+}
+
+/// Compresses a channel whose first two pixels are the given seeds, instead of
+/// pixels of the channel, and writes it to the given `BitWrite`.
+///
+/// The seeds are written to the stream, so the channel can be decompressed
+/// by `decompress_channel_with_options`, which outputs them as its first two
+/// pixels. This lets the seeds of a tile match the boundary of a neighbouring
+/// tile.
+///
+/// Returns an `InvalidInput` error if a seed is negative or greater than the
+/// maximum pixel value of `options`, as the decompressed pixels would be rejected.
+///
 /// # Panics
 ///
-/// This functions assumes that the `channel` is big enough to hold
-/// `width*height` pixels. It will panic if the `channel` is not big enough.
-fn compress_channel<W>(
+/// Panics if `channel` has less than `width*height` pixels. Its first two
+/// pixels are ignored.
+pub fn compress_channel_with_seeds<W>(
     channel: &[i32],
     width: u32,
     height: u32,
+    seed1: i32,
+    seed2: i32,
     options: CodingOptions,
     bitwrite: &mut W,
 ) -> io::Result<()>
 where
     W: BitWrite,
 {
-    let total_size: usize = width.checked_mul(height).unwrap().try_into().unwrap();
+    channel_size(width, height)?;
     assert!(
-        channel.len() >= total_size,
+        channel.len() as u64 >= width as u64 * height as u64,
         "The channel is not big enough!"
     );
+    let max_value = options.max_value();
+    if [seed1, seed2]
+        .iter()
+        .any(|seed| !(0..=max_value).contains(seed))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The seeds are outside the range of the pixel values",
+        ));
+    }
+    let rest = channel.iter().skip(2).copied();
+    let seeds = (seed1, seed2);
+    compress_channel_seeded(rest, width, height, seeds, options, &mut None, bitwrite)
+}
 
-    // Check for edge-case image dimensions.
-    match (width, height) {
-        (0, _) | (_, 0) => {
-            bitwrite.write_signed(i32::BITS, 0)?;
-            bitwrite.write_signed(i32::BITS, 0)?;
-            return Ok(());
-        }
-        (1, 1) => {
-            bitwrite.write_signed(i32::BITS, channel[0])?;
-            bitwrite.write_signed(i32::BITS, 0)?;
-            return Ok(());
-        }
-        _ => {
-            bitwrite.write_signed(i32::BITS, channel[0])?;
-            bitwrite.write_signed(i32::BITS, channel[1])?;
-        }
-    };
+/// Compresses the pixels of a channel that follow the two seed pixels.
+///
+/// The `channel` yields the pixels in raster-scan order.
+///
+/// # Panics
+///
+/// Panics if `channel` yields less than `width*height - 2` pixels.
+fn compress_channel_seeded<I, W>(
+    channel: I,
+    width: u32,
+    height: u32,
+    seeds: (i32, i32),
+    options: CodingOptions,
+    estimator: &mut Option<KEstimator>,
+    bitwrite: &mut W,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = i32>,
+    W: BitWrite,
+{
+    let total_size = channel_size(width, height)?;
+    let mut channel = channel.into_iter();
+    let mut next_pixel = || channel.next().expect("The channel is not big enough!");
 
-    let mut estimator: KEstimator = KEstimator::new(
-        options.max_context,
-        options.k_values,
-        options.periodic_count_scaling,
-    );
+    let seed_bits = verbatim_bits(options);
+    let (pixel1, pixel2) = seeds;
+    bitwrite.write_signed(seed_bits, pixel1)?;
+    bitwrite.write_signed(seed_bits, pixel2)?;
+    // The channel only holds the seeds.
+    if total_size <= 2 {
+        return Ok(());
+    }
+
+    // Ring buffer holding the previously visited pixels.
+    let ring_size = cmp::max(2 * width as usize, 2);
+    let mut ring = vec![0; ring_size];
+    ring[0] = pixel1;
+    ring[1] = pixel2;
 
-    // Proceed in raster-scan order.
-    for i in 2..total_size {
-        let (a, b) = misc::nearest_neighbours(i, width as usize).unwrap();
+    let estimator = band_estimator(estimator, options);
+
+    // The length of the current run of constant pixels, if any.
+    let mut run: Option<u32> = None;
+    let mut run_estimator = run_estimator(options);
+    let mut first_row_estimator = first_row_estimator(options);
+    let neighbours = misc::NearestNeighbourLookup::new(width as usize, height as usize);
+
+    // Proceed in raster-scan order, after the two seed pixels.
+    for (x, y) in RasterScan::new(width, height).skip(2) {
+        let i = y as usize * width as usize + x as usize;
+        let p = next_pixel();
+
+        if y == 0 && options.first_row_mode != FirstRowMode::Horizontal {
+            let left = ring[(i - 1) % ring_size];
+            encode_first_row(bitwrite, &mut first_row_estimator, options, p, left)?;
+            ring[i % ring_size] = p;
+            continue;
+        }
 
-        let p = channel[i];
-        let v1 = channel[a];
-        let v2 = channel[b];
+        let (a, b) = neighbours
+            .get_with_fallback(x as usize, y as usize)
+            .pair()
+            .expect("Only the first pixel has no neighbours!");
+        let v1 = ring[a % ring_size];
+        let v2 = ring[b % ring_size];
+        ring[i % ring_size] = p;
 
         let h = cmp::max(v1, v2);
         let l = cmp::min(v1, v2);
@@ -127,9 +419,37 @@ where
         let k = estimator.get_k(context);
         let rice_coder = RiceCoder::new(k);
 
+        if options.run_length_encoding && context == 0 {
+            if p == l {
+                run = Some(run.unwrap_or(0) + 1);
+                continue;
+            }
+
+            // The run is broken by a pixel that is outside the range, so only
+            // the second bit of its intensity is written.
+            encode_run(bitwrite, &mut run_estimator, run.take().unwrap_or(0))?;
+            let to_encode: u32 = if p < l {
+                bitwrite.write_bit(false)?;
+                (l - p - 1).try_into().unwrap()
+            } else {
+                bitwrite.write_bit(true)?;
+                (p - h - 1).try_into().unwrap()
+            };
+            rice_coder.encode(bitwrite, to_encode)?;
+            estimator.update(context, to_encode);
+            continue;
+        }
+
+        // The run ended because the context is not zero anymore.
+        if let Some(length) = run.take() {
+            encode_run(bitwrite, &mut run_estimator, length)?;
+        }
+
         if p >= l && p <= h {
             encode_intensity(bitwrite, PixelIntensity::InRange)?;
             let to_encode: u32 = (p - l).try_into().unwrap();
+            let shift = phase_in_shift(context, options.phase_in_rotation_bias);
+            let to_encode = ((to_encode as u64 + shift as u64) % (context as u64 + 1)) as u32;
             let phase_in_coder = PhaseInCoder::new(context + 1);
             phase_in_coder.encode(bitwrite, to_encode)?;
         } else if p < l {
@@ -144,11 +464,227 @@ where
             estimator.update(context, to_encode);
         }
     }
+
+    // The image ended with a run.
+    if let Some(length) = run {
+        encode_run(bitwrite, &mut run_estimator, length)?;
+    }
     Ok(())
 }
 
-/// Decompresses a channel by reading from the given `BitRead`.
-fn decompress_channel<R>(
+/// Compresses a channel as horizontal bands of `band_height` rows, the last
+/// band holding the remaining rows. Each band is compressed independently by
+/// `compress_channel`, with fresh parameter estimates.
+///
+/// A `band_height` of at least `height` compresses the channel as a single
+/// band, exactly like `compress_channel`.
+///
+/// # Panics
+///
+/// Panics if `band_height` is 0 and the channel is not empty, or if the
+/// `channel` yields less than `width*height` pixels.
+fn compress_channel_segmented<I, W>(
+    channel: I,
+    width: u32,
+    height: u32,
+    band_height: u32,
+    options: CodingOptions,
+    bitwrite: &mut W,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = i32>,
+    W: BitWrite,
+{
+    assert!(
+        band_height > 0 || height == 0,
+        "The bands must not be empty!"
+    );
+    let mut channel = channel.into_iter();
+    let mut estimator = None;
+
+    let mut row = 0;
+    loop {
+        let rows = band_height.min(height - row);
+        let band = channel.by_ref().take(width as usize * rows as usize);
+        compress_band(band, width, rows, options, &mut estimator, bitwrite)?;
+
+        row += rows;
+        if row == height {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns the number of bits that `compress_channel` would write for the given
+/// channel, without writing them.
+///
+/// # Panics
+///
+/// Panics if `channel` has less than `width*height` pixels, or if there are
+/// more than `u32::MAX` of them.
+pub fn compress_channel_dry_run(
+    channel: &[i32],
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+) -> u64 {
+    let mut counter = BitCounter::<u64, BigEndian>::new();
+    compress_channel(
+        channel.iter().copied(),
+        width,
+        height,
+        options,
+        &mut counter,
+    )
+    .expect("Counting bits cannot fail!");
+    counter.written()
+}
+
+/// The number of rows in each band sampled by `compression_worthwhile`.
+const SAMPLE_BAND_HEIGHT: u32 = 8;
+/// The maximum number of bands sampled by `compression_worthwhile`.
+const SAMPLE_BANDS: u32 = 16;
+
+/// Quickly estimates whether compressing a grayscale channel with the default
+/// coding options gives a file smaller than its raw pixels.
+///
+/// The estimate compresses up to `SAMPLE_BANDS` evenly spaced bands of
+/// `SAMPLE_BAND_HEIGHT` rows without writing them, and extrapolates their
+/// size to the whole channel, including the header.
+///
+/// # Panics
+///
+/// Panics if the channel does not have `width*height` pixels.
+pub fn compression_worthwhile<T>(channel: &[T], width: u32, height: u32) -> bool
+where
+    T: Intensity,
+{
+    assert_eq!(
+        channel.len() as u64,
+        width as u64 * height as u64,
+        "The channel does not match its dimensions!"
+    );
+    let raw_bits = channel.len() as u64 * T::PIXEL_DEPTH.bits() as u64;
+    if raw_bits == 0 {
+        return false;
+    }
+
+    let options = CodingOptions::default_for::<T>();
+    let bands = height.div_ceil(SAMPLE_BAND_HEIGHT);
+    let step = bands.div_ceil(SAMPLE_BANDS);
+
+    let mut counter = BitCounter::<u64, BigEndian>::new();
+    let mut sampled_rows = 0;
+    for band in (0..bands).step_by(step as usize) {
+        let row = band * SAMPLE_BAND_HEIGHT;
+        let rows = SAMPLE_BAND_HEIGHT.min(height - row);
+        let start = row as usize * width as usize;
+        let pixels = &channel[start..start + rows as usize * width as usize];
+
+        compress_channel(
+            pixels.iter().map(|&x| x.into()),
+            width,
+            rows,
+            options,
+            &mut counter,
+        )
+        .expect("Counting bits cannot fail!");
+        sampled_rows += rows;
+    }
+
+    let estimated_bits = counter.written() * height as u64 / sampled_rows as u64;
+    estimated_bits + HEADER_SIZE as u64 * 8 < raw_bits
+}
+
+/// Compresses a channel into the given slice, and returns the number of bytes
+/// that were written. The last byte is padded with zeros. The channel can be
+/// decompressed by `decompress_channel_with_options`, using the same options.
+///
+/// Returns `CompressIntoSliceError::SliceTooSmall` if the compressed channel
+/// does not fit in `output`.
+///
+/// # Panics
+///
+/// Panics if `channel` has less than `width*height` pixels.
+pub fn compress_channel_into_slice(
+    channel: &[i32],
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+    output: &mut [u8],
+) -> Result<usize, CompressIntoSliceError> {
+    let mut cursor = io::Cursor::new(output);
+    let mut bitwriter: BitWriter<_, BigEndian> = BitWriter::new(&mut cursor);
+
+    compress_channel(
+        channel.iter().copied(),
+        width,
+        height,
+        options,
+        &mut bitwriter,
+    )
+    .and_then(|_| bitwriter.byte_align())?;
+    Ok(cursor.position() as usize)
+}
+
+/// Compresses a single channel using the default coding options, without
+/// writing a header, for streams that record the dimensions elsewhere.
+///
+/// Returns an `InvalidInput` error if the channel does not have exactly
+/// `width*height` pixels.
+pub fn compress_raw<T, W>(channel: &[T], width: u32, height: u32, to: W) -> io::Result<()>
+where
+    T: Intensity,
+    W: Write,
+{
+    if (width as u64) * (height as u64) != channel.len() as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The channel does not match its dimensions",
+        ));
+    }
+
+    let mut bitwriter: BitWriter<W, BigEndian> = BitWriter::new(to);
+    compress_channel(
+        channel.iter().map(|&x| x.into()),
+        width,
+        height,
+        CodingOptions::default_for::<T>(),
+        &mut bitwriter,
+    )?;
+    bitwriter.byte_align()?;
+    bitwriter.flush()?;
+    Ok(())
+}
+
+/// Decompresses a channel that was compressed by `compress_raw` with the same
+/// dimensions.
+pub fn decompress_raw<T, R>(from: R, width: u32, height: u32) -> Result<Vec<T>, DecompressionError>
+where
+    T: Intensity,
+    R: Read,
+{
+    let mut bitreader: BitReader<R, BigEndian> = BitReader::new(from);
+    let options = CodingOptions::default_for::<T>();
+
+    let mut result: Vec<T> = Vec::new();
+    decompress_channel(width, height, options, &mut bitreader, |value| {
+        let value = check_pixel_value(value, options.max_value())?
+            .try_into()
+            .map_err(|_| DecompressionError::InvalidValue)?;
+        result.push(value);
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// Decompresses a channel that was compressed by `compress_channel_with_seeds`
+/// or `compress_channel_into_slice` with the same dimensions and options, by
+/// reading from the given `BitRead`.
+///
+/// Returns `DecompressionError::InvalidValue` if a pixel is negative or greater
+/// than the maximum pixel value of `options`.
+pub fn decompress_channel_with_options<R>(
     width: u32,
     height: u32,
     options: CodingOptions,
@@ -156,59 +692,153 @@ fn decompress_channel<R>(
 ) -> Result<Vec<i32>, DecompressionError>
 where
     R: BitRead,
+{
+    let mut result = Vec::new();
+    decompress_channel(width, height, options, bitread, |value| {
+        result.push(check_pixel_value(value, options.max_value())?);
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// Decompresses a channel by reading from the given `BitRead`.
+///
+/// The decoded pixels are passed to `output` in raster-scan order. Only the
+/// last `2*width` pixels are kept in memory, so the caller decides whether the pixels are
+/// stored or discarded.
+fn decompress_channel<R, F>(
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+    bitread: &mut R,
+    output: F,
+) -> Result<(), DecompressionError>
+where
+    R: BitRead,
+    F: FnMut(i32) -> Result<(), DecompressionError>,
+{
+    decompress_band(width, height, options, &mut None, bitread, output)
+}
+
+/// Decompresses a band of a channel like `decompress_channel`, reusing the
+/// estimator of the previous band, if any. See `band_estimator`.
+fn decompress_band<R, F>(
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+    estimator: &mut Option<KEstimator>,
+    bitread: &mut R,
+    mut output: F,
+) -> Result<(), DecompressionError>
+where
+    R: BitRead,
+    F: FnMut(i32) -> Result<(), DecompressionError>,
 {
     // Parse the first two pixels.
-    let pixel1: i32 = bitread.read_signed(i32::BITS)?;
-    let pixel2: i32 = bitread.read_signed(i32::BITS)?;
+    let seed_bits = verbatim_bits(options);
+    let pixel1: i32 = bitread.read_signed(seed_bits)?;
+    let pixel2: i32 = bitread.read_signed(seed_bits)?;
 
     // Handle edge-case dimensions.
     match (width, height) {
         (0, _) | (_, 0) => {
-            return Ok(vec![]);
+            return Ok(());
         }
         (1, 1) => {
-            return Ok(vec![pixel1]);
+            return output(pixel1);
         }
         _ => (),
     };
 
-    // Create the pixel buffer.
-    let total_size: usize = width
-        .checked_mul(height)
-        .ok_or(DecompressionError::InvalidDimensions)?
-        .try_into()
-        .map_err(|_| DecompressionError::InvalidDimensions)?;
-
-    let mut buf = vec![0; total_size];
-    buf[0] = pixel1;
-    buf[1] = pixel2;
-
-    let mut estimator: KEstimator = KEstimator::new(
-        options.max_context,
-        options.k_values,
-        options.periodic_count_scaling,
-    );
+    // The pixels are indexed by a `usize`.
+    let total_size =
+        channel_size(width, height).map_err(|_| DecompressionError::InvalidDimensions)?;
+
+    output(pixel1)?;
+    output(pixel2)?;
+    // The channel only holds the seeds.
+    if total_size == 2 {
+        return Ok(());
+    }
+
+    // Ring buffer holding the previously decoded pixels.
+    let ring_size = cmp::max(2 * width as usize, 2);
+    let mut ring = vec![0; ring_size];
+    ring[0] = pixel1;
+    ring[1] = pixel2;
+
+    let estimator = band_estimator(estimator, options);
+
+    // The number of pixels left in the current run of constant pixels, and
+    // whether the previous pixel ended a run.
+    let mut remaining_run: u32 = 0;
+    let mut after_run = false;
+    let mut run_estimator = run_estimator(options);
+    let mut first_row_estimator = first_row_estimator(options);
+    let neighbours = misc::NearestNeighbourLookup::new(width as usize, height as usize);
+
+    // Proceed in raster-scan order, after the two seed pixels.
+    for (x, y) in RasterScan::new(width, height).skip(2) {
+        let i = y as usize * width as usize + x as usize;
+        if y == 0 && options.first_row_mode != FirstRowMode::Horizontal {
+            let left = ring[(i - 1) % ring_size];
+            let pixel_value = decode_first_row(bitread, &mut first_row_estimator, options, left)?;
+            ring[i % ring_size] = pixel_value;
+            output(pixel_value)?;
+            continue;
+        }
 
-    // Proceed in raster-scan order.
-    for i in 2..total_size {
-        let (a, b) = misc::nearest_neighbours(i, width as usize).unwrap();
+        let (a, b) = neighbours
+            .get_with_fallback(x as usize, y as usize)
+            .pair()
+            .expect("Only the first pixel has no neighbours!");
 
-        let v1 = buf[a];
-        let v2 = buf[b];
+        let v1 = ring[a % ring_size];
+        let v2 = ring[b % ring_size];
 
         let h = cmp::max(v1, v2);
         let l = cmp::min(v1, v2);
-        let context: u32 = (h - l).try_into().unwrap();
+        let context: u32 = h.abs_diff(l);
         let k = estimator.get_k(context);
-        let rice_coder = RiceCoder::new(k);
+        let rice_coder = RiceCoder::new_checked(k).ok_or(DecompressionError::InvalidValue)?;
+
+        let intensity = if options.run_length_encoding && context == 0 {
+            if remaining_run == 0 && !after_run {
+                remaining_run = decode_run(bitread, &mut run_estimator)?;
+            }
+            if remaining_run > 0 {
+                remaining_run -= 1;
+                after_run = remaining_run == 0;
+                ring[i % ring_size] = l;
+                output(l)?;
+                continue;
+            }
 
-        let intensity = decode_intensity(bitread)?;
+            // A pixel that breaks a run is always outside the range.
+            after_run = false;
+            match bitread.read_bit()? {
+                true => PixelIntensity::AboveRange,
+                false => PixelIntensity::BelowRange,
+            }
+        } else {
+            // A run can only contain pixels with a zero context.
+            if remaining_run > 0 {
+                return Err(DecompressionError::InvalidValue);
+            }
+            after_run = false;
+            decode_intensity(bitread)?
+        };
 
         let pixel_value = match intensity {
             PixelIntensity::InRange => {
-                let phase_in_coder = PhaseInCoder::new(context + 1);
-                let p: i32 = phase_in_coder
-                    .decode(bitread)?
+                let phase_in_coder = context
+                    .checked_add(1)
+                    .and_then(PhaseInCoder::new_checked)
+                    .ok_or(DecompressionError::InvalidValue)?;
+                let shift = phase_in_shift(context, options.phase_in_rotation_bias);
+                let decoded = phase_in_coder.decode(bitread)? as u64;
+                let n = context as u64 + 1;
+                let p: i32 = ((decoded + n - shift as u64) % n)
                     .try_into()
                     .map_err(|_| DecompressionError::InvalidValue)?;
                 p.checked_add(l).ok_or(DecompressionError::ValueOverflow)?
@@ -242,43 +872,218 @@ where
                     .ok_or(DecompressionError::ValueOverflow)?
             }
         };
-        buf[i] = pixel_value;
+        ring[i % ring_size] = pixel_value;
+        output(pixel_value)?;
     }
-    Ok(buf)
+    Ok(())
 }
 
-impl<T> CompressDecompress for ImageBuffer<Luma<T>, Vec<T>>
+/// Decompresses a channel that was compressed by `compress_channel_segmented`
+/// with the same `band_height`.
+fn decompress_channel_segmented<R, F>(
+    width: u32,
+    height: u32,
+    band_height: u32,
+    options: CodingOptions,
+    bitread: &mut R,
+    mut output: F,
+) -> Result<(), DecompressionError>
 where
-    Luma<T>: Pixel<Subpixel = T>,
-    T: Intensity,
+    R: BitRead,
+    F: FnMut(i32) -> Result<(), DecompressionError>,
 {
-    fn compress<W>(&self, mut to: W) -> io::Result<()>
-    where
-        W: Write,
-    {
-        let (width, height) = self.dimensions();
-        write_header(
+    assert!(
+        band_height > 0 || height == 0,
+        "The bands must not be empty!"
+    );
+    let mut estimator = None;
+
+    let mut row = 0;
+    loop {
+        let rows = band_height.min(height - row);
+        decompress_band(width, rows, options, &mut estimator, bitread, &mut output)?;
+
+        row += rows;
+        if row == height {
+            return Ok(());
+        }
+    }
+}
+
+/// Checks that the header describes an image with the given color type,
+/// pixel intensity type and dimensions.
+fn check_header_matches<T>(
+    header: &Header,
+    color_type: ColorType,
+    (width, height): (u32, u32),
+) -> io::Result<()>
+where
+    T: Intensity,
+{
+    if header.color_type != color_type
+        || header.pixel_depth != T::PIXEL_DEPTH
+        || (header.width, header.height) != (width, height)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The header does not describe the image",
+        ));
+    }
+    Ok(())
+}
+
+/// Compresses the pixels of a grayscale image, without the header.
+fn compress_luma<T, C, W>(
+    image: &ImageBuffer<Luma<T>, C>,
+    to: W,
+    options: CodingOptions,
+) -> io::Result<()>
+where
+    Luma<T>: Pixel<Subpixel = T>,
+    T: Intensity,
+    C: Deref<Target = [T]>,
+    W: Write,
+{
+    let (width, height) = image.dimensions();
+    let mut bitwriter: BitWriter<W, BigEndian> = BitWriter::new(to);
+    let channel = image.as_raw().iter().map(|&x| x.into());
+    let band_height = options.band_height.unwrap_or(height);
+
+    compress_channel_segmented(channel, width, height, band_height, options, &mut bitwriter)?;
+    if options.output_buffer_mode.channel_flush {
+        bitwriter.flush()?;
+    }
+    bitwriter.byte_align()?;
+    if options.output_buffer_mode.final_flush {
+        bitwriter.flush()?;
+    }
+    Ok(())
+}
+
+/// Returns the dimensions of the given YCoCg channel of a `width x height`
+/// image, as the Co and Cg channels may be downsampled.
+fn ycocg_channel_dimensions(
+    channel: u8,
+    width: u32,
+    height: u32,
+    options: CodingOptions,
+) -> (u32, u32) {
+    match options.chroma_subsampling {
+        ChromaSubsampling::Yuv420 if channel != 0 => chroma_dimensions(width, height),
+        _ => (width, height),
+    }
+}
+
+/// Compresses the pixels of an RGB image, without the header.
+fn compress_rgb<T, C, W>(
+    image: &ImageBuffer<Rgb<T>, C>,
+    to: W,
+    options: CodingOptions,
+) -> io::Result<()>
+where
+    Rgb<T>: Pixel<Subpixel = T>,
+    T: Intensity,
+    C: Deref<Target = [T]>,
+    W: Write,
+{
+    let (width, height) = image.dimensions();
+
+    // Convert the pixels to the YCoCg color space on the fly.
+    let pixels = image.as_raw();
+    let ycocg = || {
+        pixels
+            .chunks_exact(3)
+            .map(|p| rgb_to_ycocg(p[0].into(), p[1].into(), p[2].into()))
+    };
+
+    let mut bitwriter: BitWriter<W, BigEndian> = BitWriter::new(to);
+    let band_height = options.band_height.unwrap_or(height);
+
+    for &channel in options.channel_order.iter() {
+        let (channel_width, channel_height) =
+            ycocg_channel_dimensions(channel, width, height, options);
+        let channel = ycocg().map(|c| [c.0, c.1, c.2][channel as usize]);
+
+        if (channel_width, channel_height) == (width, height) {
+            compress_channel_segmented(
+                channel,
+                width,
+                height,
+                band_height,
+                options,
+                &mut bitwriter,
+            )?;
+        } else {
+            let downsampled = downsample_chroma(&channel.collect::<Vec<_>>(), width, height);
+            compress_channel_segmented(
+                downsampled,
+                channel_width,
+                channel_height,
+                band_height,
+                options,
+                &mut bitwriter,
+            )?;
+        }
+        if options.output_buffer_mode.channel_flush {
+            bitwriter.flush()?;
+        }
+    }
+    bitwriter.byte_align()?;
+    if options.output_buffer_mode.final_flush {
+        bitwriter.flush()?;
+    }
+    Ok(())
+}
+
+impl<T, C> CompressDecompress for ImageBuffer<Luma<T>, C>
+where
+    Luma<T>: Pixel<Subpixel = T>,
+    T: Intensity,
+    C: Deref<Target = [T]> + From<Vec<T>>,
+{
+    fn compress<W>(&self, to: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        self.compress_with_options(to, CodingOptions::default_for::<T>())
+    }
+
+    fn compress_with_options<W>(&self, mut to: W, options: CodingOptions) -> io::Result<()>
+    where
+        W: Write,
+    {
+        options.validate_for::<T>(self.as_raw())?;
+
+        let (width, height) = self.dimensions();
+        write_header(
             Header {
                 color_type: ColorType::Gray,
                 pixel_depth: T::PIXEL_DEPTH,
                 width,
                 height,
+                channel_order: options.channel_order,
+                run_length_encoding: options.run_length_encoding,
+                effective_bit_depth: options.effective_bit_depth,
+                periodic_count_scaling: options.periodic_count_scaling,
+                first_row_mode: options.first_row_mode,
+                phase_in_rotation_bias: options.phase_in_rotation_bias,
+                band_height: options.band_height,
+                quality: options.quality,
+                chroma_subsampling: options.chroma_subsampling,
             },
             &mut to,
         )?;
+        compress_luma(self, to, options)
+    }
 
-        let mut bitwriter: BitWriter<W, BigEndian> = BitWriter::new(to);
-        let options = CodingOptions {
-            max_context: T::MAX_CONTEXT,
-            k_values: T::K_VALUES,
-            periodic_count_scaling: T::COUNT_SCALING,
-        };
-        let channel: Vec<i32> = self.as_raw().iter().map(|&x| x.into()).collect();
-
-        compress_channel(&channel, width, height, options, &mut bitwriter)?;
-        bitwriter.byte_align()?;
-        bitwriter.flush()?;
-        Ok(())
+    fn compress_with_header<W>(&self, to: W, header: &Header) -> io::Result<()>
+    where
+        W: Write,
+    {
+        check_header_matches::<T>(header, ColorType::Gray, self.dimensions())?;
+        let options = CodingOptions::from_header::<T>(header);
+        options.validate_for::<T>(self.as_raw())?;
+        compress_luma(self, to, options)
     }
 
     fn decompress_with_header<R>(from: R, header: &Header) -> Result<Self, DecompressionError>
@@ -286,43 +1091,55 @@ where
         Self: Sized,
         R: Read,
     {
-        if header.color_type != ColorType::Gray {
-            return Err(DecompressionError::InvalidColorType);
-        }
-        if header.pixel_depth != T::PIXEL_DEPTH {
-            return Err(DecompressionError::InvalidPixelDepth);
+        let expected = (ColorType::Gray, T::PIXEL_DEPTH);
+        let found = (header.color_type, header.pixel_depth);
+        if found != expected {
+            return Err(DecompressionError::ImageTypeMismatch { expected, found });
         }
 
         let mut bitreader: BitReader<R, BigEndian> = BitReader::new(from);
-        let options = CodingOptions {
-            max_context: T::MAX_CONTEXT,
-            k_values: T::K_VALUES,
-            periodic_count_scaling: T::COUNT_SCALING,
-        };
-        let channel = decompress_channel(header.width, header.height, options, &mut bitreader)?;
-
-        // Channel is Vec<i32>, convert back to T.
-        let mut result: Vec<T> = vec![T::default(); channel.len()];
-        for (i, &value) in channel.iter().enumerate() {
-            result[i] = value
-                .try_into()
-                .map_err(|_| DecompressionError::InvalidValue)?;
-        }
+        let options = CodingOptions::from_header::<T>(header);
+        // Convert the decoded values back to T.
+        let mut result: Vec<T> = Vec::new();
+        decompress_channel_segmented(
+            header.width,
+            header.height,
+            options.band_height.unwrap_or(header.height),
+            options,
+            &mut bitreader,
+            |value| {
+                let value = check_pixel_value(value, options.max_value())?
+                    .try_into()
+                    .map_err(|_| DecompressionError::InvalidValue)?;
+                result.push(value);
+                Ok(())
+            },
+        )?;
 
-        let image = ImageBuffer::from_raw(header.width, header.height, result).unwrap();
+        let image = ImageBuffer::from_raw(header.width, header.height, result.into()).unwrap();
         Ok(image)
     }
 }
 
-impl<T> CompressDecompress for ImageBuffer<Rgb<T>, Vec<T>>
+impl<T, C> CompressDecompress for ImageBuffer<Rgb<T>, C>
 where
     Rgb<T>: Pixel<Subpixel = T>,
     T: Intensity,
+    C: Deref<Target = [T]> + From<Vec<T>>,
 {
-    fn compress<W>(&self, mut to: W) -> io::Result<()>
+    fn compress<W>(&self, to: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        self.compress_with_options(to, CodingOptions::default_for::<T>())
+    }
+
+    fn compress_with_options<W>(&self, mut to: W, options: CodingOptions) -> io::Result<()>
     where
         W: Write,
     {
+        options.validate_for::<T>(self.as_raw())?;
+
         let (width, height) = self.dimensions();
         write_header(
             Header {
@@ -330,44 +1147,29 @@ where
                 pixel_depth: T::PIXEL_DEPTH,
                 width,
                 height,
+                channel_order: options.channel_order,
+                run_length_encoding: options.run_length_encoding,
+                effective_bit_depth: options.effective_bit_depth,
+                periodic_count_scaling: options.periodic_count_scaling,
+                first_row_mode: options.first_row_mode,
+                phase_in_rotation_bias: options.phase_in_rotation_bias,
+                band_height: options.band_height,
+                quality: options.quality,
+                chroma_subsampling: options.chroma_subsampling,
             },
             &mut to,
         )?;
+        compress_rgb(self, to, options)
+    }
 
-        let num_pixels = (width as usize) * (height as usize);
-        let pixels = self.as_raw();
-
-        let (mut y, mut co, mut cg) = (
-            vec![0; num_pixels],
-            vec![0; num_pixels],
-            vec![0; num_pixels],
-        );
-
-        for i in 0..num_pixels {
-            let current = i * 3;
-            let (ly, lco, lcg) = rgb_to_ycocg(
-                pixels[current].into(),
-                pixels[current + 1].into(),
-                pixels[current + 2].into(),
-            );
-            y[i] = ly;
-            co[i] = lco;
-            cg[i] = lcg;
-        }
-
-        let mut bitwriter: BitWriter<W, BigEndian> = BitWriter::new(to);
-        let options = CodingOptions {
-            max_context: T::MAX_CONTEXT,
-            k_values: T::K_VALUES,
-            periodic_count_scaling: T::COUNT_SCALING,
-        };
-
-        compress_channel(&y, width, height, options, &mut bitwriter)?;
-        compress_channel(&co, width, height, options, &mut bitwriter)?;
-        compress_channel(&cg, width, height, options, &mut bitwriter)?;
-        bitwriter.byte_align()?;
-        bitwriter.flush()?;
-        Ok(())
+    fn compress_with_header<W>(&self, to: W, header: &Header) -> io::Result<()>
+    where
+        W: Write,
+    {
+        check_header_matches::<T>(header, ColorType::Rgb, self.dimensions())?;
+        let options = CodingOptions::from_header::<T>(header);
+        options.validate_for::<T>(self.as_raw())?;
+        compress_rgb(self, to, options)
     }
 
     fn decompress_with_header<R>(from: R, header: &Header) -> Result<Self, DecompressionError>
@@ -375,23 +1177,42 @@ where
         Self: Sized,
         R: Read,
     {
-        if header.color_type != ColorType::Rgb {
-            return Err(DecompressionError::InvalidColorType);
-        }
-        if header.pixel_depth != T::PIXEL_DEPTH {
-            return Err(DecompressionError::InvalidPixelDepth);
+        let expected = (ColorType::Rgb, T::PIXEL_DEPTH);
+        let found = (header.color_type, header.pixel_depth);
+        if found != expected {
+            return Err(DecompressionError::ImageTypeMismatch { expected, found });
         }
 
         let mut bitreader: BitReader<R, BigEndian> = BitReader::new(from);
-        let options = CodingOptions {
-            max_context: T::MAX_CONTEXT,
-            k_values: T::K_VALUES,
-            periodic_count_scaling: T::COUNT_SCALING,
-        };
+        let options = CodingOptions::from_header::<T>(header);
+
+        // The channels are stored in the order recorded in the header.
+        let mut channels: [Vec<i32>; 3] = Default::default();
+        for &channel in options.channel_order.iter() {
+            let (width, height) =
+                ycocg_channel_dimensions(channel, header.width, header.height, options);
+            let buf = &mut channels[channel as usize];
+            decompress_channel_segmented(
+                width,
+                height,
+                options.band_height.unwrap_or(header.height),
+                options,
+                &mut bitreader,
+                |value| {
+                    buf.push(value);
+                    Ok(())
+                },
+            )?;
+        }
+        let [y, mut co, mut cg] = channels;
 
-        let y = decompress_channel(header.width, header.height, options, &mut bitreader)?;
-        let co = decompress_channel(header.width, header.height, options, &mut bitreader)?;
-        let cg = decompress_channel(header.width, header.height, options, &mut bitreader)?;
+        // The color error of the upsampled chroma may push the RGB values out
+        // of range, so they are clamped instead of rejected.
+        let subsampled = options.chroma_subsampling == ChromaSubsampling::Yuv420;
+        if subsampled {
+            co = upsample_chroma(&co, header.width, header.height);
+            cg = upsample_chroma(&cg, header.width, header.height);
+        }
 
         let num_pixels = (header.width as usize) * (header.height as usize);
         let buf_size = num_pixels
@@ -401,11 +1222,20 @@ where
         let mut buf = vec![T::default(); buf_size];
         for i in 0..num_pixels {
             let (r, g, b) = ycocg_to_rgb(y[i], co[i], cg[i]);
+            let max_value = options.max_value();
+            let check = |value: i32| {
+                if subsampled {
+                    Ok(value.clamp(0, max_value))
+                } else {
+                    check_pixel_value(value, max_value)
+                }
+            };
+            let (r, g, b) = (check(r)?, check(g)?, check(b)?);
             buf[i * 3] = r.try_into().map_err(|_| DecompressionError::InvalidValue)?;
             buf[i * 3 + 1] = g.try_into().map_err(|_| DecompressionError::InvalidValue)?;
             buf[i * 3 + 2] = b.try_into().map_err(|_| DecompressionError::InvalidValue)?;
         }
-        Ok(ImageBuffer::from_raw(header.width, header.height, buf).unwrap())
+        Ok(ImageBuffer::from_raw(header.width, header.height, buf.into()).unwrap())
     }
 }
 
@@ -417,33 +1247,161 @@ where
     image.compress(to)
 }
 
-pub fn decompress_image<R>(mut from: R) -> Result<DynamicImage, DecompressionError>
+/// Compresses the image like `compress_image`, and returns the number of bytes
+/// that were written, which can be used to compute the compression ratio.
+pub fn compress_image_counted<W, T>(to: W, image: T) -> io::Result<usize>
+where
+    W: Write,
+    T: CompressDecompress,
+{
+    let mut writer = misc::CountingWriter::new(to);
+    image.compress(&mut writer)?;
+    Ok(writer.count())
+}
+
+/// Compresses a `DynamicImage` using the default coding options.
+///
+/// Returns an `InvalidInput` error for color types that felics does not
+/// support, such as images with an alpha channel.
+pub fn compress_dynamic_image<W>(to: W, image: &DynamicImage) -> io::Result<()>
+where
+    W: Write,
+{
+    match image {
+        DynamicImage::ImageLuma8(image) => image.compress(to),
+        DynamicImage::ImageLuma16(image) => image.compress(to),
+        DynamicImage::ImageRgb8(image) => image.compress(to),
+        DynamicImage::ImageRgb16(image) => image.compress(to),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported image format: {:?}", image.color()),
+        )),
+    }
+}
+
+pub fn decompress_image<R>(from: R) -> Result<DynamicImage, DecompressionError>
+where
+    R: Read,
+{
+    let (image, _header) = decompress_image_with_info(from)?;
+    Ok(image)
+}
+
+/// Decompresses the image like `decompress_image`, and also returns the header
+/// that was parsed, with the original pixel depth and coding options.
+pub fn decompress_image_with_info<R>(
+    mut from: R,
+) -> Result<(DynamicImage, Header), DecompressionError>
 where
     R: Read,
 {
     let header = read_header(&mut from)?;
+    let image = decompress_image_with_header(from, &header)?;
+    Ok((image, header))
+}
 
+/// Decompresses the image that follows the given header.
+fn decompress_image_with_header<R>(
+    from: R,
+    header: &Header,
+) -> Result<DynamicImage, DecompressionError>
+where
+    R: Read,
+{
     let result = match (&header.color_type, &header.pixel_depth) {
         (ColorType::Gray, PixelDepth::Eight) => {
-            DynamicImage::ImageLuma8(CompressDecompress::decompress_with_header(from, &header)?)
+            DynamicImage::ImageLuma8(CompressDecompress::decompress_with_header(from, header)?)
         }
         (ColorType::Gray, PixelDepth::Sixteen) => {
-            DynamicImage::ImageLuma16(CompressDecompress::decompress_with_header(from, &header)?)
+            DynamicImage::ImageLuma16(CompressDecompress::decompress_with_header(from, header)?)
         }
         (ColorType::Rgb, PixelDepth::Eight) => {
-            DynamicImage::ImageRgb8(CompressDecompress::decompress_with_header(from, &header)?)
+            DynamicImage::ImageRgb8(CompressDecompress::decompress_with_header(from, header)?)
         }
         (ColorType::Rgb, PixelDepth::Sixteen) => {
-            DynamicImage::ImageRgb16(CompressDecompress::decompress_with_header(from, &header)?)
+            DynamicImage::ImageRgb16(CompressDecompress::decompress_with_header(from, header)?)
         }
     };
     Ok(result)
 }
 
+/// Checks the structural integrity of a felics stream without producing the image.
+///
+/// All the channels are decoded with the same checks as `decompress_image`, but the
+/// decoded pixels are discarded instead of being stored. For grayscale images, the
+/// decoded values are also checked against the pixel depth. For RGB images, the
+/// range of the reconstructed RGB values is not checked, as that would require
+/// keeping all the channels in memory.
+///
+/// Returns the header of the stream if it is valid.
+pub fn validate_stream<R>(mut from: R) -> Result<Header, DecompressionError>
+where
+    R: Read,
+{
+    let header = read_header(&mut from)?;
+
+    match header.pixel_depth {
+        PixelDepth::Eight => validate_channels::<u8, R>(from, &header)?,
+        PixelDepth::Sixteen => validate_channels::<u16, R>(from, &header)?,
+    }
+    Ok(header)
+}
+
+/// Decodes the channels of a felics stream and discards the decoded pixels.
+fn validate_channels<T, R>(from: R, header: &Header) -> Result<(), DecompressionError>
+where
+    T: Intensity,
+    R: Read,
+{
+    let mut bitreader: BitReader<R, BigEndian> = BitReader::new(from);
+    let options = CodingOptions::from_header::<T>(header);
+    let (width, height) = (header.width, header.height);
+    let band_height = options.band_height.unwrap_or(height);
+
+    match header.color_type {
+        ColorType::Gray => decompress_channel_segmented(
+            width,
+            height,
+            band_height,
+            options,
+            &mut bitreader,
+            |value| {
+                check_pixel_value(value, options.max_value())?;
+                Ok(())
+            },
+        ),
+        ColorType::Rgb => {
+            for channel in options.channel_order {
+                let (width, height) = ycocg_channel_dimensions(channel, width, height, options);
+                decompress_channel_segmented(
+                    width,
+                    height,
+                    band_height,
+                    options,
+                    &mut bitreader,
+                    |_| Ok(()),
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{CompressDecompress, Pixel};
-    use image::{GrayImage, ImageBuffer, Luma, Rgb};
+    use super::{
+        compress_channel, compress_channel_dry_run, compress_channel_into_slice,
+        compress_channel_segmented, compress_channel_with_seeds, compress_image_counted,
+        compress_raw, compression_worthwhile, decode_intensity, decompress_channel,
+        decompress_channel_segmented, decompress_channel_with_options, decompress_image_with_info,
+        decompress_raw, encode_intensity, phase_in_shift, read_header, unzigzag, validate_stream,
+        verbatim_bits, zigzag, ChromaSubsampling, CodingOptions, ColorType, CompressDecompress,
+        CompressIntoSliceError, DecompressionError, EffectiveBitDepth, FirstRowMode,
+        OutputBufferMode, PhaseInCoder, Pixel, PixelDepth, PixelIntensity, Quality, FORMAT_VERSION,
+        HEADER_SIZE,
+    };
+    use bitstream_io::{BigEndian, BitReader, BitRecorder, BitWrite, BitWriter};
+    use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
     use rand::{
         self,
         distributions::{Distribution, Standard},
@@ -451,7 +1409,8 @@ mod test {
         Rng,
     };
     use std::fmt::Debug;
-    use std::io::Cursor;
+    use std::io::{self, Cursor};
+    use std::sync::Arc;
 
     #[test]
     fn test_compression_zero_width() {
@@ -462,6 +1421,62 @@ mod test {
         assert_eq!(image, decompressed);
     }
 
+    // Both pixels of a two pixel image are stored verbatim.
+    #[test]
+    fn test_compression_two_pixels() {
+        for (width, height) in [(2, 1), (1, 2)] {
+            let image = GrayImage::from_raw(width, height, vec![17u8, 200u8]).unwrap();
+            let mut sink = Vec::new();
+            image.compress(&mut sink).unwrap();
+            let decompressed = GrayImage::decompress(&mut Cursor::new(sink)).unwrap();
+            assert_eq!(image, decompressed);
+        }
+    }
+
+    // Locks in the wire format of the start of the header of a compressed
+    // image, see the `format` module.
+    #[test]
+    fn test_compressed_header_bytes() {
+        let image = GrayImage::from_raw(1, 1, vec![0xA5u8]).unwrap();
+        let mut sink = Vec::new();
+        image.compress(&mut sink).unwrap();
+
+        assert_eq!(&sink[0..4], b"FLCS");
+        assert_eq!(sink[4], 0, "color type");
+        assert_eq!(sink[5], 0, "pixel depth");
+        assert_eq!(&sink[6..10], [0, 0, 0, 1], "width");
+        assert_eq!(&sink[10..14], [0, 0, 0, 1], "height");
+        assert_eq!(sink[14], FORMAT_VERSION);
+    }
+
+    // The third pixel of a 2x2 image is the first one coded using its nearest
+    // neighbours, which are the two seed pixels, and the fourth pixel depends
+    // on the third one.
+    #[test]
+    fn test_compression_two_by_two() {
+        for bits in 0..16u32 {
+            let extreme = |i: u32| bits >> i & 1 == 1;
+
+            let pixels: Vec<u8> = (0..4)
+                .map(|i| if extreme(i) { u8::MAX } else { 0 })
+                .collect();
+            let image = GrayImage::from_raw(2, 2, pixels).unwrap();
+            let mut sink = Vec::new();
+            image.compress(&mut sink).unwrap();
+            assert_eq!(GrayImage::decompress(Cursor::new(sink)).unwrap(), image);
+
+            let pixels: Vec<u16> = (0..4)
+                .map(|i| if extreme(i) { u16::MAX } else { 0 })
+                .collect();
+            let image = ImageBuffer::<Luma<u16>, _>::from_raw(2, 2, pixels).unwrap();
+            let mut sink = Vec::new();
+            image.compress(&mut sink).unwrap();
+            let decompressed: ImageBuffer<Luma<u16>, Vec<u16>> =
+                CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(decompressed, image);
+        }
+    }
+
     // Returns a random image with the given dimensions.
     fn random_grayscale<T>(
         width: u32,
@@ -541,6 +1556,918 @@ mod test {
         assert_eq!(image, decompressed);
     }
 
+    #[test]
+    fn test_compression_decompression_containers() {
+        let mut rng = rand::thread_rng();
+
+        let image = random_grayscale::<u8>(31, 17, &mut rng);
+        let boxed: ImageBuffer<Luma<u8>, Box<[u8]>> =
+            ImageBuffer::from_raw(31, 17, image.as_raw().clone().into()).unwrap();
+        let mut sink = Vec::new();
+        boxed.compress(&mut sink).unwrap();
+        let decompressed: ImageBuffer<Luma<u8>, Box<[u8]>> =
+            CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+        assert_eq!(boxed, decompressed);
+
+        let image = random_rgb::<u16>(31, 17, &mut rng);
+        let shared: ImageBuffer<Rgb<u16>, Arc<[u16]>> =
+            ImageBuffer::from_raw(31, 17, image.as_raw().clone().into()).unwrap();
+        let mut sink = Vec::new();
+        shared.compress(&mut sink).unwrap();
+        let decompressed: ImageBuffer<Rgb<u16>, Arc<[u16]>> =
+            CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+        assert_eq!(shared, decompressed);
+    }
+
+    #[test]
+    fn test_compression_decompression_channel_order() {
+        let mut rng = rand::thread_rng();
+        let image = random_rgb::<u8>(31, 17, &mut rng);
+
+        let mut options = CodingOptions::default_for::<u8>();
+        options.channel_order = [2, 0, 1];
+
+        let mut sink = Vec::new();
+        image.compress_with_options(&mut sink, options).unwrap();
+        let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+        assert_eq!(image, decompressed);
+    }
+
+    #[test]
+    fn test_compression_decompression_count_scaling() {
+        let mut rng = rand::thread_rng();
+        let image = random_grayscale::<u8>(64, 64, &mut rng);
+
+        for threshold in [None, Some(1), Some(100_000)] {
+            let mut options = CodingOptions::default_for::<u8>();
+            options.periodic_count_scaling = threshold;
+
+            let mut sink = Vec::new();
+            image.compress_with_options(&mut sink, options).unwrap();
+            let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(image, decompressed);
+        }
+
+        let mut options = CodingOptions::default_for::<u8>();
+        options.periodic_count_scaling = Some(0);
+        assert!(image.compress_with_options(Vec::new(), options).is_err());
+    }
+
+    // The k values of a quality preset are recovered from the header.
+    #[test]
+    fn test_compression_decompression_quality() {
+        let mut rng = rand::thread_rng();
+        let gray = random_grayscale::<u16>(64, 64, &mut rng);
+        let rgb = random_rgb::<u8>(32, 16, &mut rng);
+
+        for quality in [Quality::Fast, Quality::Balanced, Quality::Best] {
+            let mut sink = Vec::new();
+            let options = CodingOptions::for_quality::<u16>(quality);
+            gray.compress_with_options(&mut sink, options).unwrap();
+            assert_eq!(
+                read_header(&mut Cursor::new(&sink)).unwrap().quality,
+                Some(quality)
+            );
+            let decompressed: ImageBuffer<Luma<u16>, Vec<u16>> =
+                CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(gray, decompressed);
+
+            let mut sink = Vec::new();
+            let options = CodingOptions::for_quality::<u8>(quality);
+            rgb.compress_with_options(&mut sink, options).unwrap();
+            assert_eq!(RgbImage::decompress(Cursor::new(sink)).unwrap(), rgb);
+        }
+    }
+
+    #[test]
+    fn test_compression_decompression_first_row_mode() {
+        let mut rng = rand::thread_rng();
+        let gray = random_grayscale::<u8>(40, 9, &mut rng);
+        let rgb = random_rgb::<u16>(40, 9, &mut rng);
+
+        for first_row_mode in [
+            FirstRowMode::Horizontal,
+            FirstRowMode::Verbatim,
+            FirstRowMode::Delta,
+        ] {
+            for run_length_encoding in [false, true] {
+                let mut options = CodingOptions::default_for::<u8>();
+                options.first_row_mode = first_row_mode;
+                options.run_length_encoding = run_length_encoding;
+                let mut sink = Vec::new();
+                gray.compress_with_options(&mut sink, options).unwrap();
+                let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+                assert_eq!(gray, decompressed);
+
+                let mut options = CodingOptions::default_for::<u16>();
+                options.first_row_mode = first_row_mode;
+                options.run_length_encoding = run_length_encoding;
+                let mut sink = Vec::new();
+                rgb.compress_with_options(&mut sink, options).unwrap();
+                let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+                assert_eq!(rgb, decompressed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compression_decompression_phase_in_rotation_bias() {
+        let mut rng = rand::thread_rng();
+        let gray = random_grayscale::<u8>(40, 9, &mut rng);
+        let rgb = random_rgb::<u16>(40, 9, &mut rng);
+
+        for bias in [i32::MIN, -300, -1, 0, 1, 7, 300, i32::MAX] {
+            let mut options = CodingOptions::default_for::<u8>();
+            options.phase_in_rotation_bias = bias;
+            let mut sink = Vec::new();
+            gray.compress_with_options(&mut sink, options).unwrap();
+            let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(gray, decompressed);
+
+            let mut options = CodingOptions::default_for::<u16>();
+            options.phase_in_rotation_bias = bias;
+            let mut sink = Vec::new();
+            rgb.compress_with_options(&mut sink, options).unwrap();
+            let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(rgb, decompressed);
+        }
+    }
+
+    #[test]
+    fn test_compression_decompression_band_height() {
+        let mut rng = rand::thread_rng();
+        let gray = random_grayscale::<u16>(31, 9, &mut rng);
+        let rgb = random_rgb::<u8>(31, 9, &mut rng);
+
+        for band_height in [1, 2, 4, 8, 9, 100] {
+            let mut options = CodingOptions::default_for::<u16>();
+            options.band_height = Some(band_height);
+            let mut sink = Vec::new();
+            gray.compress_with_options(&mut sink, options).unwrap();
+            let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(gray, decompressed);
+
+            let mut options = CodingOptions::default_for::<u8>();
+            options.band_height = Some(band_height);
+            let mut sink = Vec::new();
+            rgb.compress_with_options(&mut sink, options).unwrap();
+            let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+            assert_eq!(rgb, decompressed);
+        }
+    }
+
+    // Bands of a few pixels must not pay for the estimator of every context
+    // of a `u16` channel, because the band height is read from the header.
+    #[test]
+    fn test_compression_decompression_small_bands() {
+        let mut rng = rand::thread_rng();
+        let start = std::time::Instant::now();
+        for width in [2, 3] {
+            let gray = random_grayscale::<u16>(width, 2000, &mut rng);
+            for band_height in [1, 2] {
+                let mut options = CodingOptions::default_for::<u16>();
+                options.band_height = Some(band_height);
+                let mut sink = Vec::new();
+                gray.compress_with_options(&mut sink, options).unwrap();
+                let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+                assert_eq!(gray, decompressed);
+            }
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_compress_raw() {
+        let mut rng = rand::thread_rng();
+        let image = random_grayscale::<u16>(17, 11, &mut rng);
+
+        let mut raw = Vec::new();
+        compress_raw(image.as_raw(), 17, 11, &mut raw).unwrap();
+        let decompressed: Vec<u16> = decompress_raw(Cursor::new(&raw), 17, 11).unwrap();
+        assert_eq!(&decompressed, image.as_raw());
+
+        // The raw stream is the compressed image without its header.
+        let mut compressed = Vec::new();
+        image.compress(&mut compressed).unwrap();
+        assert!(compressed.ends_with(&raw));
+
+        assert!(compress_raw(image.as_raw(), 17, 10, &mut Vec::new()).is_err());
+        assert!(compress_raw::<u8, _>(&[], 0, 5, &mut raw).is_ok());
+    }
+
+    #[test]
+    fn test_chroma_subsampling() {
+        let options = CodingOptions {
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+            ..CodingOptions::default_for::<u8>()
+        };
+
+        // Gray pixels have no chroma, so they survive the subsampling.
+        let mut rng = rand::thread_rng();
+        for (width, height) in [(0, 4), (1, 1), (7, 5), (16, 9)] {
+            let image = RgbImage::from_fn(width, height, |_, _| {
+                let value = rng.gen();
+                Rgb([value, value, value])
+            });
+            let mut sink = Vec::new();
+            image.compress_with_options(&mut sink, options).unwrap();
+            let (decompressed, header) = decompress_image_with_info(Cursor::new(&sink)).unwrap();
+            assert_eq!(header.chroma_subsampling, ChromaSubsampling::Yuv420);
+            assert_eq!(decompressed.into_rgb8(), image);
+            assert!(validate_stream(Cursor::new(&sink)).is_ok());
+        }
+
+        // Smooth colors are approximated closely, by a smaller file.
+        let image = RgbImage::from_fn(64, 48, |x, y| {
+            Rgb([(x * 3) as u8, (y * 5) as u8, (x + y) as u8 + 17])
+        });
+        let mut lossless = Vec::new();
+        image.compress(&mut lossless).unwrap();
+        let mut lossy = Vec::new();
+        image.compress_with_options(&mut lossy, options).unwrap();
+        assert!(lossy.len() < lossless.len());
+
+        let decompressed = RgbImage::decompress(Cursor::new(&lossy)).unwrap();
+        let max_error = image
+            .as_raw()
+            .iter()
+            .zip(decompressed.as_raw())
+            .map(|(&a, &b)| (a as i32 - b as i32).abs())
+            .max()
+            .unwrap();
+        assert!(max_error <= 4, "The color error is too big: {}", max_error);
+    }
+
+    #[test]
+    fn test_compression_worthwhile() {
+        // A constant image compresses to a few bits per pixel.
+        let constant = vec![128u8; 64 * 64];
+        assert!(compression_worthwhile(&constant, 64, 64));
+
+        let gradient: Vec<u16> = (0..100 * 300).map(|i| (i % 100) as u16 * 7).collect();
+        assert!(compression_worthwhile(&gradient, 100, 300));
+
+        // Uniform noise cannot be compressed.
+        let mut rng = rand::thread_rng();
+        let noise: Vec<u8> = (0..64 * 64).map(|_| rng.gen()).collect();
+        assert!(!compression_worthwhile(&noise, 64, 64));
+
+        // The header outweighs the pixels of tiny images.
+        assert!(!compression_worthwhile(&[7u8; 4], 2, 2));
+        assert!(!compression_worthwhile::<u8>(&[], 0, 10));
+    }
+
+    #[test]
+    fn test_compress_channel_too_large() {
+        let options = CodingOptions::default_for::<u16>();
+        let mut recorder = BitRecorder::<u64, BigEndian>::new();
+        let err =
+            compress_channel(std::iter::empty(), 65536, 65536, options, &mut recorder).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(recorder.written(), 0);
+
+        let err = compress_channel_with_seeds(&[], 65536, 65536, 0, 0, options, &mut recorder)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_compress_channel_with_seeds() {
+        let mut rng = rand::thread_rng();
+        let options = CodingOptions::default_for::<u8>();
+        let channel: Vec<i32> = (0..23 * 9).map(|_| rng.gen_range(0..256)).collect();
+
+        let compress = |seed1, seed2| {
+            let mut bitwriter = BitWriter::endian(Vec::new(), BigEndian);
+            compress_channel_with_seeds(&channel, 23, 9, seed1, seed2, options, &mut bitwriter)
+                .unwrap();
+            bitwriter.byte_align().unwrap();
+            bitwriter.into_writer()
+        };
+
+        // The channel's own seeds give the same stream as `compress_channel`.
+        let mut bitwriter = BitWriter::endian(Vec::new(), BigEndian);
+        compress_channel(channel.iter().copied(), 23, 9, options, &mut bitwriter).unwrap();
+        bitwriter.byte_align().unwrap();
+        assert_eq!(compress(channel[0], channel[1]), bitwriter.into_writer());
+
+        let compressed = compress(255, 0);
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&compressed));
+        let decoded = decompress_channel_with_options(23, 9, options, &mut bitreader).unwrap();
+        assert_eq!(decoded[..2], [255, 0]);
+        assert_eq!(decoded[2..], channel[2..]);
+
+        // The seeds must be valid pixel values.
+        for (seed1, seed2) in [(-1, 0), (0, 256), (i32::MIN, i32::MAX)] {
+            let mut recorder = BitRecorder::<u64, BigEndian>::new();
+            let err =
+                compress_channel_with_seeds(&channel, 23, 9, seed1, seed2, options, &mut recorder)
+                    .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(recorder.written(), 0);
+        }
+    }
+
+    // Locks in the bits written by the raster-scan coding loop.
+    #[test]
+    fn test_compress_channel_raster_scan() {
+        let channel: Vec<i32> = (0..7 * 5)
+            .map(|i| i % 7 / 2 * 9 + i / 14 * 4 + i % 5 / 4 * 7)
+            .collect();
+        let expected: [(bool, FirstRowMode, &[u8]); 3] = [
+            (
+                false,
+                FirstRowMode::Horizontal,
+                &[
+                    0, 0, 18, 58, 191, 8, 58, 52, 99, 213, 81, 126, 67, 70, 65, 143, 83, 104, 238,
+                    44, 177, 124, 232,
+                ],
+            ),
+            (
+                true,
+                FirstRowMode::Horizontal,
+                &[
+                    0, 0, 0, 0, 36, 117, 126, 16, 114, 52, 98, 234, 168, 191, 33, 163, 32, 197,
+                    212, 218, 59, 139, 44, 95, 58,
+                ],
+            ),
+            (
+                true,
+                FirstRowMode::Delta,
+                &[
+                    0, 0, 18, 3, 193, 177, 0, 1, 35, 70, 15, 73, 81, 126, 67, 70, 65, 139, 169,
+                    180, 119, 22, 88, 190, 116,
+                ],
+            ),
+        ];
+
+        for (run_length_encoding, first_row_mode, bytes) in expected {
+            let options = CodingOptions {
+                run_length_encoding,
+                first_row_mode,
+                ..CodingOptions::default_for::<u8>()
+            };
+            let mut bitwriter = BitWriter::endian(Vec::new(), BigEndian);
+            compress_channel(channel.iter().copied(), 7, 5, options, &mut bitwriter).unwrap();
+            bitwriter.byte_align().unwrap();
+            assert_eq!(bitwriter.into_writer(), bytes);
+            assert_eq!(channel_round_trip(&channel, 7, 5, options), channel);
+        }
+    }
+
+    // Alternating red and blue pixels make the Co channel swing between its
+    // extremes, so its pixels are coded in the maximum context.
+    #[test]
+    fn test_compress_rgb_maximum_chroma_context() {
+        let image = RgbImage::from_fn(9, 6, |x, y| match (x + y) % 2 {
+            0 => Rgb([255, 0, 0]),
+            _ => Rgb([0, 0, 255]),
+        });
+        let co: Vec<i32> = image.pixels().map(|p| p[0] as i32 - p[2] as i32).collect();
+        assert_eq!(co[..2], [255, -255]);
+
+        let options = CodingOptions::default_for::<u8>();
+        assert_eq!(options.max_context(), (255 - -255) as u32);
+        assert_eq!(channel_round_trip(&co, 9, 6, options), co);
+
+        let mut sink = Vec::new();
+        image.compress(&mut sink).unwrap();
+        assert_eq!(RgbImage::decompress(Cursor::new(sink)).unwrap(), image);
+    }
+
+    /// A writer that counts the calls to `flush`.
+    #[derive(Default)]
+    struct FlushCounter {
+        bytes: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl io::Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_output_buffer_mode() {
+        let mut rng = rand::thread_rng();
+        let image = random_rgb::<u8>(13, 7, &mut rng);
+
+        let mut expected = Vec::new();
+        image.compress(&mut expected).unwrap();
+
+        for (channel_flush, final_flush, flushes) in [
+            (false, true, 1),
+            (true, true, 4),
+            (true, false, 3),
+            (false, false, 0),
+        ] {
+            let options = CodingOptions {
+                output_buffer_mode: OutputBufferMode {
+                    channel_flush,
+                    final_flush,
+                },
+                ..CodingOptions::default_for::<u8>()
+            };
+            let mut writer = FlushCounter::default();
+            image.compress_with_options(&mut writer, options).unwrap();
+            assert_eq!(writer.flushes, flushes);
+            assert_eq!(writer.bytes, expected);
+        }
+    }
+
+    #[test]
+    fn test_compress_channel_segmented() {
+        let mut rng = rand::thread_rng();
+        let channel: Vec<i32> = (0..45 * 23).map(|_| rng.gen_range(0..256)).collect();
+        let options = CodingOptions::default_for::<u8>();
+
+        let to_bytes = |recorder: BitRecorder<u64, BigEndian>| {
+            let mut bitwriter = BitWriter::endian(Vec::new(), BigEndian);
+            recorder.playback(&mut bitwriter).unwrap();
+            bitwriter.byte_align().unwrap();
+            bitwriter.into_writer()
+        };
+
+        // A single band is the same as the whole channel.
+        let mut whole = BitRecorder::<u64, BigEndian>::new();
+        compress_channel(channel.iter().copied(), 45, 23, options, &mut whole).unwrap();
+        let mut single = BitRecorder::<u64, BigEndian>::new();
+        compress_channel_segmented(channel.iter().copied(), 45, 23, 23, options, &mut single)
+            .unwrap();
+        assert_eq!(to_bytes(whole), to_bytes(single));
+
+        // The bands are the concatenation of the independently compressed rows.
+        let mut bands = BitRecorder::<u64, BigEndian>::new();
+        for rows in channel.chunks(45 * 10) {
+            let height = (rows.len() / 45) as u32;
+            compress_channel(rows.iter().copied(), 45, height, options, &mut bands).unwrap();
+        }
+        let mut segmented = BitRecorder::<u64, BigEndian>::new();
+        compress_channel_segmented(channel.iter().copied(), 45, 23, 10, options, &mut segmented)
+            .unwrap();
+        let bytes = to_bytes(segmented);
+        assert_eq!(to_bytes(bands), bytes);
+
+        let mut bitreader = BitReader::endian(Cursor::new(bytes), BigEndian);
+        let mut decompressed = Vec::new();
+        decompress_channel_segmented(45, 23, 10, options, &mut bitreader, |value| {
+            decompressed.push(value);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(channel, decompressed);
+    }
+
+    // A positive bias gives the shortest codewords to the values near L.
+    #[test]
+    fn test_phase_in_rotation_bias() {
+        assert_eq!(phase_in_shift(10, 0), 0);
+        assert_eq!(phase_in_shift(10, 3), 3);
+        assert_eq!(phase_in_shift(10, 100), 10);
+        assert_eq!(phase_in_shift(10, -3), 8);
+        assert_eq!(phase_in_shift(10, i32::MIN), 1);
+        assert_eq!(phase_in_shift(0, 5), 0);
+
+        // The shortest codewords of [0, 6] are given to 3 without a bias.
+        let channel = [10, 16, 10];
+        let mut options = CodingOptions::default_for::<u8>();
+        let lengths: Vec<u64> = [0, 3]
+            .iter()
+            .map(|&bias| {
+                options.phase_in_rotation_bias = bias;
+                compress_channel_dry_run(&channel, 3, 1, options)
+            })
+            .collect();
+        assert!(lengths[1] < lengths[0]);
+    }
+
+    #[test]
+    fn test_compress_decompress_with_header() {
+        let mut rng = rand::thread_rng();
+        let image = random_rgb::<u8>(40, 30, &mut rng);
+        let mut options = CodingOptions::default_for::<u8>();
+        options.run_length_encoding = true;
+        options.channel_order = [2, 1, 0];
+
+        let mut expected = Vec::new();
+        image.compress_with_options(&mut expected, options).unwrap();
+
+        // The header is read once, and then used for the image data.
+        let mut from = Cursor::new(&expected);
+        let header = read_header(&mut from).unwrap();
+        assert_eq!((header.width, header.height), (40, 30));
+        let decompressed = RgbImage::decompress_with_header(&mut from, &header).unwrap();
+        assert_eq!(image, decompressed);
+
+        let mut sink = Vec::new();
+        image.compress_with_header(&mut sink, &header).unwrap();
+        assert_eq!(sink, expected[expected.len() - sink.len()..]);
+
+        let mut other = header;
+        other.width = 41;
+        assert!(image.compress_with_header(Vec::new(), &other).is_err());
+        other.width = 40;
+        other.color_type = ColorType::Gray;
+        assert!(image.compress_with_header(Vec::new(), &other).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_intensity() {
+        let intensities = || {
+            [
+                (PixelIntensity::InRange, 1),
+                (PixelIntensity::AboveRange, 2),
+                (PixelIntensity::BelowRange, 2),
+            ]
+        };
+
+        for (intensity, length) in intensities() {
+            let mut recorder = BitRecorder::<u32, BigEndian>::new();
+            encode_intensity(&mut recorder, intensity).unwrap();
+            assert_eq!(recorder.written(), length);
+        }
+
+        let mut sink = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut sink);
+        for (intensity, _) in intensities() {
+            encode_intensity(&mut bitwriter, intensity).unwrap();
+        }
+        bitwriter.byte_align().unwrap();
+
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&sink));
+        for (intensity, _) in intensities() {
+            assert_eq!(decode_intensity(&mut bitreader).unwrap(), intensity);
+        }
+    }
+
+    #[test]
+    fn test_decode_intensity_truncated() {
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&[]));
+        let err = decode_intensity(&mut bitreader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // The last bit of the input starts a two bit code.
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&[0b1111_1110]));
+        for _ in 0..7 {
+            assert_eq!(
+                decode_intensity(&mut bitreader).unwrap(),
+                PixelIntensity::InRange
+            );
+        }
+        let err = decode_intensity(&mut bitreader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_zigzag() {
+        let values = [0, -1, 1, -2, 2, i32::MIN / 2, i32::MAX / 2];
+        let expected = [0, 1, 2, 3, 4, (1 << 31) - 1, (1 << 31) - 2];
+        for (&value, &expected) in values.iter().zip(expected.iter()) {
+            assert_eq!(zigzag(value), expected);
+            assert_eq!(unzigzag(expected), value);
+        }
+    }
+
+    // Returns an image with large constant regions.
+    fn blocky_grayscale(width: u32, height: u32, rng: &mut ThreadRng) -> GrayImage {
+        let mut image = GrayImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let value = if (x / 13 + y / 7) % 3 == 0 {
+                    rng.gen()
+                } else {
+                    ((x / 13) * 40) as u8
+                };
+                image.put_pixel(x, y, Luma([value]));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_compression_decompression_run_length() {
+        let mut rng = rand::thread_rng();
+        let mut options = CodingOptions::default_for::<u8>();
+        options.run_length_encoding = true;
+
+        let dimensions = [(1, 1), (2, 1), (1, 40), (3, 3), (100, 40), (64, 64)];
+        for (width, height) in dimensions {
+            for image in [
+                blocky_grayscale(width, height, &mut rng),
+                GrayImage::new(width, height),
+                random_grayscale(width, height, &mut rng),
+            ] {
+                let mut sink = Vec::new();
+                image.compress_with_options(&mut sink, options).unwrap();
+                let decompressed: GrayImage =
+                    CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+                assert_eq!(image, decompressed);
+            }
+        }
+
+        let image = random_rgb::<u16>(30, 30, &mut rng);
+        let mut options = CodingOptions::default_for::<u16>();
+        options.run_length_encoding = true;
+        let mut sink = Vec::new();
+        image.compress_with_options(&mut sink, options).unwrap();
+        let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+        assert_eq!(image, decompressed);
+    }
+
+    #[test]
+    fn test_run_length_constant_image() {
+        let image = GrayImage::from_pixel(512, 512, Luma([77]));
+        let mut options = CodingOptions::default_for::<u8>();
+
+        let mut plain = Vec::new();
+        image.compress_with_options(&mut plain, options).unwrap();
+
+        options.run_length_encoding = true;
+        let mut run_length = Vec::new();
+        image
+            .compress_with_options(&mut run_length, options)
+            .unwrap();
+
+        assert!(run_length.len() * 100 < plain.len());
+    }
+
+    #[test]
+    fn test_compression_decompression_effective_bit_depth() {
+        let mut rng = rand::thread_rng();
+        let mut options = CodingOptions::default_for::<u16>();
+        options.effective_bit_depth = Some(EffectiveBitDepth(12));
+
+        let mut image = random_grayscale::<u16>(50, 37, &mut rng);
+        image.pixels_mut().for_each(|p| p.0[0] >>= 4);
+        let mut sink = Vec::new();
+        image.compress_with_options(&mut sink, options).unwrap();
+        let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+        assert_eq!(image, decompressed);
+
+        let mut image = random_rgb::<u16>(50, 37, &mut rng);
+        image
+            .pixels_mut()
+            .for_each(|p| p.0.iter_mut().for_each(|x| *x >>= 4));
+        let mut sink = Vec::new();
+        image.compress_with_options(&mut sink, options).unwrap();
+        let decompressed = CompressDecompress::decompress(Cursor::new(sink)).unwrap();
+        assert_eq!(image, decompressed);
+
+        // Pixels that do not fit the effective bit depth are rejected.
+        image.put_pixel(3, 3, Rgb([0, 4096, 0]));
+        assert!(image.compress_with_options(Vec::new(), options).is_err());
+    }
+
+    // Compresses and decompresses a channel, returning the decoded pixels.
+    fn channel_round_trip(
+        channel: &[i32],
+        width: u32,
+        height: u32,
+        options: CodingOptions,
+    ) -> Vec<i32> {
+        let mut sink = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut sink);
+        compress_channel(
+            channel.iter().copied(),
+            width,
+            height,
+            options,
+            &mut bitwriter,
+        )
+        .unwrap();
+        bitwriter.byte_align().unwrap();
+
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&sink));
+        let mut decoded = Vec::new();
+        decompress_channel(width, height, options, &mut bitreader, |value| {
+            decoded.push(value);
+            Ok(())
+        })
+        .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_decompress_channel_truncated() {
+        let options = CodingOptions::default_for::<u8>();
+        // The first pixel is 9 bits long, and it is followed by another 9 bits.
+        assert_eq!(verbatim_bits(options), 9);
+        for truncated in [vec![], vec![42], vec![0, 42]] {
+            let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(truncated));
+            let result = decompress_channel(1, 1, options, &mut bitreader, |_| {
+                panic!("No pixel should be decoded!")
+            });
+            match result {
+                Err(DecompressionError::IoError(err)) => {
+                    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof)
+                }
+                other => panic!("Expected an UnexpectedEof error, got {:?}", other),
+            }
+        }
+    }
+
+    // The first two pixels are the extremes of the intensity range, so the
+    // third pixel is coded in the maximum context.
+    #[test]
+    fn test_compress_channel_maximum_context() {
+        let options = CodingOptions::default_for::<u8>();
+        for third in [0, 1, 127, 254, 255] {
+            let channel = [0, u8::MAX as i32, third];
+            assert_eq!(channel_round_trip(&channel, 3, 1, options), channel);
+        }
+
+        let options = CodingOptions::default_for::<u16>();
+        for third in [0, u16::MAX as i32 / 2, u16::MAX as i32] {
+            let channel = [0, u16::MAX as i32, third];
+            assert_eq!(channel_round_trip(&channel, 3, 1, options), channel);
+        }
+    }
+
+    // In a single column, the neighbours of a pixel are the two pixels above it.
+    #[test]
+    fn test_compression_single_column() {
+        let options = CodingOptions::default_for::<u8>();
+        let channel = [10, 20, 15, 17, 30];
+
+        // The pixel at row 2 is in the range [10, 20] of rows 0 and 1, and
+        // the pixel at row 3 is in the range [15, 20] of rows 1 and 2.
+        let mut recorder = BitRecorder::<u32, BigEndian>::new();
+        compress_channel(channel[..4].iter().copied(), 1, 4, options, &mut recorder).unwrap();
+        let expected = 2 * verbatim_bits(options)
+            + 1
+            + PhaseInCoder::new(11).code_length(5)
+            + 1
+            + PhaseInCoder::new(6).code_length(2);
+        assert_eq!(recorder.written(), expected);
+
+        assert_eq!(channel_round_trip(&channel, 1, 5, options), channel);
+
+        let image = GrayImage::from_raw(1, 5, vec![10, 20, 15, 17, 30]).unwrap();
+        let mut sink = Vec::new();
+        image.compress(&mut sink).unwrap();
+        let decompressed = GrayImage::decompress(&mut Cursor::new(sink)).unwrap();
+        assert_eq!(image, decompressed);
+    }
+
+    #[test]
+    fn test_compress_channel_dry_run() {
+        let mut rng = rand::thread_rng();
+        let image = random_grayscale::<u8>(45, 23, &mut rng);
+        let channel: Vec<i32> = image.iter().map(|&x| x as i32).collect();
+
+        for run_length_encoding in [false, true] {
+            let mut options = CodingOptions::default_for::<u8>();
+            options.run_length_encoding = run_length_encoding;
+
+            let mut recorder = BitRecorder::<u64, BigEndian>::new();
+            compress_channel(channel.iter().copied(), 45, 23, options, &mut recorder).unwrap();
+            assert_eq!(
+                compress_channel_dry_run(&channel, 45, 23, options),
+                recorder.written()
+            );
+        }
+    }
+
+    #[test]
+    fn test_compress_channel_into_slice() {
+        let mut rng = rand::thread_rng();
+        let image = random_grayscale::<u8>(45, 23, &mut rng);
+        let channel: Vec<i32> = image.iter().map(|&x| x as i32).collect();
+        let options = CodingOptions::default_for::<u8>();
+
+        let mut expected = Vec::new();
+        let mut bitwriter = BitWriter::<_, BigEndian>::new(&mut expected);
+        compress_channel(channel.iter().copied(), 45, 23, options, &mut bitwriter).unwrap();
+        bitwriter.byte_align().unwrap();
+
+        let mut output = vec![0; expected.len() + 10];
+        let written = compress_channel_into_slice(&channel, 45, 23, options, &mut output).unwrap();
+        assert_eq!(&output[..written], expected);
+
+        let mut output = vec![0; expected.len() - 1];
+        assert!(matches!(
+            compress_channel_into_slice(&channel, 45, 23, options, &mut output),
+            Err(CompressIntoSliceError::SliceTooSmall)
+        ));
+
+        // A bigger slice would not help.
+        let mut output = vec![0; 1024];
+        assert!(matches!(
+            compress_channel_into_slice(&[], 65536, 65536, options, &mut output),
+            Err(CompressIntoSliceError::IoError(err)) if err.kind() == io::ErrorKind::InvalidInput
+        ));
+
+        // The channel is decompressed using the options it was compressed with.
+        let options = CodingOptions {
+            run_length_encoding: true,
+            first_row_mode: FirstRowMode::Delta,
+            ..CodingOptions::for_quality::<u8>(Quality::Fast)
+        };
+        let mut output = vec![0; 2 * expected.len()];
+        let written = compress_channel_into_slice(&channel, 45, 23, options, &mut output).unwrap();
+        let mut bitreader = BitReader::<_, BigEndian>::new(Cursor::new(&output[..written]));
+        let decompressed = decompress_channel_with_options(45, 23, options, &mut bitreader);
+        assert_eq!(decompressed.unwrap(), channel);
+    }
+
+    #[test]
+    fn test_compress_image_counted() {
+        let mut rng = rand::thread_rng();
+        let image = random_rgb::<u8>(40, 30, &mut rng);
+
+        let mut sink = Vec::new();
+        let count = compress_image_counted(&mut sink, image).unwrap();
+        assert_eq!(count, sink.len());
+    }
+
+    #[test]
+    fn test_image_type_mismatch() {
+        let image = GrayImage::new(4, 3);
+        let mut compressed = Vec::new();
+        image.compress(&mut compressed).unwrap();
+
+        let err = RgbImage::decompress(Cursor::new(&compressed)).unwrap_err();
+        assert!(matches!(
+            err,
+            DecompressionError::ImageTypeMismatch {
+                expected: (ColorType::Rgb, PixelDepth::Eight),
+                found: (ColorType::Gray, PixelDepth::Eight),
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Expected RGB 8-bit pixels, got Gray 8-bit pixels"
+        );
+
+        let result: Result<ImageBuffer<Luma<u16>, Vec<u16>>, _> =
+            CompressDecompress::decompress(Cursor::new(&compressed));
+        assert!(matches!(
+            result,
+            Err(DecompressionError::ImageTypeMismatch {
+                expected: (ColorType::Gray, PixelDepth::Sixteen),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decompress_image_with_info() {
+        let mut rng = rand::thread_rng();
+        let image = random_rgb::<u16>(40, 30, &mut rng);
+
+        let mut compressed = Vec::new();
+        image.compress(&mut compressed).unwrap();
+        let (decompressed, header) = decompress_image_with_info(Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.color_type, ColorType::Rgb);
+        assert_eq!(header.pixel_depth, PixelDepth::Sixteen);
+        assert_eq!((header.width, header.height), (40, 30));
+        assert_eq!(decompressed.into_rgb16(), image);
+    }
+
+    #[test]
+    fn test_decoded_value_out_of_range() {
+        let image = ImageBuffer::<Luma<u16>, Vec<u16>>::from_pixel(1, 1, Luma([4095]));
+        let mut options = CodingOptions::default_for::<u16>();
+        options.effective_bit_depth = Some(EffectiveBitDepth(12));
+
+        let mut sink = Vec::new();
+        image.compress_with_options(&mut sink, options).unwrap();
+        // Set the sign bit of the seed pixel, which follows the header.
+        sink[HEADER_SIZE] |= 0x80;
+
+        let result: Result<ImageBuffer<Luma<u16>, Vec<u16>>, _> =
+            CompressDecompress::decompress(Cursor::new(&sink));
+        assert!(matches!(result, Err(DecompressionError::InvalidValue)));
+        assert!(matches!(
+            validate_stream(Cursor::new(&sink)),
+            Err(DecompressionError::InvalidValue)
+        ));
+    }
+
+    #[test]
+    fn test_validate_stream() {
+        let mut rng = rand::thread_rng();
+        let image = random_rgb::<u16>(40, 23, &mut rng);
+
+        let mut sink = Vec::new();
+        image.compress(&mut sink).unwrap();
+
+        let header = validate_stream(Cursor::new(&sink)).unwrap();
+        assert_eq!(header.width, 40);
+        assert_eq!(header.height, 23);
+
+        // A truncated stream is not valid.
+        sink.truncate(sink.len() / 2);
+        assert!(validate_stream(Cursor::new(&sink)).is_err());
+    }
+
     #[test]
     #[ignore]
     fn test_compression_decompression_intensive() {
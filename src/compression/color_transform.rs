@@ -29,6 +29,7 @@ pub fn ycocg_to_rgb(y: i32, co: i32, cg: i32) -> (i32, i32, i32) {
 mod test {
     use super::*;
     use crate::compression::traits::Intensity;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use std::cmp::{max, min};
 
     #[test]
@@ -70,6 +71,13 @@ mod test {
         assert!(max_context_y <= u8::MAX_CONTEXT);
         assert!(max_context_co <= u8::MAX_CONTEXT);
         assert!(max_context_cg <= u8::MAX_CONTEXT);
+
+        // The chroma channels are signed, so their contexts do reach
+        // MAX_CONTEXT, which cannot be any smaller.
+        assert_eq!((min_co, max_co), (-255, 255));
+        assert_eq!((min_cg, max_cg), (-255, 255));
+        assert_eq!(max_context_co, u8::MAX_CONTEXT);
+        assert_eq!(max_context_cg, u8::MAX_CONTEXT);
     }
 
     #[test]
@@ -117,5 +125,33 @@ mod test {
         assert!(max_context_y <= u16::MAX_CONTEXT);
         assert!(max_context_co <= u16::MAX_CONTEXT);
         assert!(max_context_cg <= u16::MAX_CONTEXT);
+        assert_eq!(max_context_co, u16::MAX_CONTEXT);
+    }
+
+    #[test]
+    fn test_color_transform16_random() {
+        let mut rng = StdRng::seed_from_u64(0xFE11C5);
+
+        for _ in 0..100_000 {
+            let (r, g, b): (u16, u16, u16) = rng.gen();
+            let (y, co, cg) = rgb_to_ycocg(r.into(), g.into(), b.into());
+
+            // The same transform computed using i64, which cannot overflow
+            // for 16-bit values, must give the same result.
+            let (r64, g64, b64) = (r as i64, g as i64, b as i64);
+            let co64 = r64 - b64;
+            let t64 = b64 + co64 / 2;
+            let cg64 = g64 - t64;
+            let y64 = t64 + cg64 / 2;
+            assert_eq!((y as i64, co as i64, cg as i64), (y64, co64, cg64));
+
+            let max_value = u16::MAX as i32;
+            assert!((0..=max_value).contains(&y));
+            assert!((-max_value..=max_value).contains(&co));
+            assert!((-max_value..=max_value).contains(&cg));
+
+            let (rn, gn, bn) = ycocg_to_rgb(y, co, cg);
+            assert_eq!((rn, gn, bn), (r.into(), g.into(), b.into()));
+        }
     }
 }
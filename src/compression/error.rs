@@ -1,5 +1,6 @@
+use super::format::{ColorType, PixelDepth};
 use std::convert::From;
-use std::io;
+use std::{fmt, io};
 
 #[derive(Debug)]
 pub enum DecompressionError {
@@ -14,8 +15,25 @@ pub enum DecompressionError {
     InvalidColorType,
     /// There was an attempt to decode an image with an invalid pixel depth.
     InvalidPixelDepth,
+    /// The header describes another color type or pixel depth than the
+    /// image type that was requested.
+    ImageTypeMismatch {
+        expected: (ColorType, PixelDepth),
+        found: (ColorType, PixelDepth),
+    },
     /// The signature of the file does not match a felics file.
     InvalidSignature,
+    /// The channel order is not a permutation of the image channels.
+    InvalidChannelOrder,
+    /// A flag in the header is neither 0 nor 1.
+    InvalidFlag,
+    /// The first row mode in the header is unknown.
+    InvalidFirstRowMode,
+    /// The chroma subsampling mode in the header is unknown.
+    InvalidChromaSubsampling,
+    /// The file was written with a format version that is not supported,
+    /// usually by a newer version of felics.
+    UnsupportedVersion(u8),
 }
 
 impl From<io::Error> for DecompressionError {
@@ -23,3 +41,64 @@ impl From<io::Error> for DecompressionError {
         DecompressionError::IoError(err)
     }
 }
+
+impl fmt::Display for DecompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressionError::IoError(err) => write!(f, "{}", err),
+            DecompressionError::InvalidValue => {
+                write!(f, "A decoded value does not fit the pixel depth")
+            }
+            DecompressionError::ValueOverflow => write!(f, "A decoded value overflowed"),
+            DecompressionError::InvalidDimensions => write!(f, "The dimensions are invalid"),
+            DecompressionError::InvalidColorType => write!(f, "The color type is invalid"),
+            DecompressionError::InvalidPixelDepth => write!(f, "The pixel depth is invalid"),
+            DecompressionError::ImageTypeMismatch { expected, found } => write!(
+                f,
+                "Expected {} {} pixels, got {} {} pixels",
+                expected.0, expected.1, found.0, found.1
+            ),
+            DecompressionError::InvalidSignature => write!(f, "The file is not a felics file"),
+            DecompressionError::InvalidChannelOrder => write!(f, "The channel order is invalid"),
+            DecompressionError::InvalidFlag => write!(f, "A flag in the header is invalid"),
+            DecompressionError::InvalidFirstRowMode => write!(f, "The first row mode is invalid"),
+            DecompressionError::InvalidChromaSubsampling => {
+                write!(f, "The chroma subsampling mode is invalid")
+            }
+            DecompressionError::UnsupportedVersion(version) => {
+                write!(f, "The format version {} is not supported", version)
+            }
+        }
+    }
+}
+
+/// An error of `compress_channel_into_slice`.
+#[derive(Debug)]
+pub enum CompressIntoSliceError {
+    /// The output slice is too small to hold the compressed data.
+    SliceTooSmall,
+    /// The channel could not be compressed, for example because it is too
+    /// large. A bigger output slice does not help.
+    IoError(io::Error),
+}
+
+impl From<io::Error> for CompressIntoSliceError {
+    fn from(err: io::Error) -> CompressIntoSliceError {
+        // Writing to a full slice fails with `WriteZero`.
+        match err.kind() {
+            io::ErrorKind::WriteZero => CompressIntoSliceError::SliceTooSmall,
+            _ => CompressIntoSliceError::IoError(err),
+        }
+    }
+}
+
+impl fmt::Display for CompressIntoSliceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressIntoSliceError::SliceTooSmall => {
+                write!(f, "The output slice is too small")
+            }
+            CompressIntoSliceError::IoError(err) => write!(f, "{}", err),
+        }
+    }
+}
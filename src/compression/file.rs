@@ -0,0 +1,130 @@
+use super::error::DecompressionError;
+use super::format::{read_header, Header};
+use super::misc::CountingWriter;
+use super::{compress_dynamic_image, decompress_image_with_header};
+use image::ImageFormat;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Returns the path of the temporary file the output is written to, before
+/// it is renamed to `path`.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+/// Writes to a temporary file using `write`, then renames it to `path`, so that
+/// `path` is never left with a partially written file.
+fn write_atomically<T, E, F>(path: &Path, write: F) -> Result<T, E>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<T, E>,
+    E: From<io::Error>,
+{
+    let temporary = temporary_path(path);
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+
+    let result = write(&mut writer).and_then(|value| {
+        writer.flush()?;
+        Ok(value)
+    });
+    drop(writer);
+
+    match result {
+        Ok(value) => {
+            fs::rename(&temporary, path)?;
+            Ok(value)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&temporary);
+            Err(err)
+        }
+    }
+}
+
+/// Compresses the image file at `input_path` to a felics file at `output_path`.
+/// The format of the input file is detected from its extension.
+///
+/// Returns the size of the compressed file.
+pub fn compress_file(input_path: &Path, output_path: &Path) -> io::Result<u64> {
+    let image =
+        image::open(input_path).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    write_atomically(output_path, |writer| {
+        let mut writer = CountingWriter::new(writer);
+        compress_dynamic_image(&mut writer, &image)?;
+        Ok(writer.count() as u64)
+    })
+}
+
+/// Decompresses the felics file at `input_path` to an image file at `output_path`.
+/// The format of the output file is determined using its extension.
+///
+/// Returns the header of the felics file.
+pub fn decompress_file(
+    input_path: &Path,
+    output_path: &Path,
+) -> Result<Header, DecompressionError> {
+    let format = ImageFormat::from_path(output_path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let mut reader = BufReader::new(File::open(input_path)?);
+    let header = read_header(&mut reader)?;
+    let image = decompress_image_with_header(reader, &header)?;
+
+    write_atomically(output_path, |writer| {
+        image.write_to(writer, format).map_err(io::Error::other)
+    })?;
+    Ok(header)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compress_file, decompress_file, temporary_path};
+    use crate::compression::{ColorType, DecompressionError};
+    use image::RgbImage;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_temporary_path() {
+        assert_eq!(
+            temporary_path(Path::new("images/out.felics")),
+            Path::new("images/out.felics.part")
+        );
+    }
+
+    #[test]
+    fn test_compress_decompress_file() {
+        let dir = std::env::temp_dir().join(format!("felics-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let image = RgbImage::from_fn(23, 17, |x, y| image::Rgb([x as u8, y as u8, (x * y) as u8]));
+        let input = dir.join("input.png");
+        image.save(&input).unwrap();
+
+        let compressed = dir.join("image.felics");
+        let size = compress_file(&input, &compressed).unwrap();
+        assert_eq!(size, fs::metadata(&compressed).unwrap().len());
+        assert!(!temporary_path(&compressed).exists());
+
+        let output = dir.join("output.png");
+        let header = decompress_file(&compressed, &output).unwrap();
+        assert_eq!(header.color_type, ColorType::Rgb);
+        assert_eq!((header.width, header.height), (23, 17));
+        assert_eq!(image::open(&output).unwrap().into_rgb8(), image);
+
+        // A failed decompression leaves no output behind.
+        fs::write(&compressed, b"not a felics file").unwrap();
+        let output = dir.join("invalid.png");
+        assert!(matches!(
+            decompress_file(&compressed, &output),
+            Err(DecompressionError::InvalidSignature)
+        ));
+        assert!(!output.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use super::decompress_image_with_header;
+use super::error::DecompressionError;
+use super::format::read_header;
+use image::DynamicImage;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+/// Computes a SHA-256 fingerprint of the pixels of a felics stream.
+///
+/// The hash is computed over the dimensions, the color type and the pixel
+/// depth of the image, followed by the decompressed samples in row-major
+/// order, with all the numbers in big-endian. The rest of the header is not
+/// hashed, so two files with the same image have the same fingerprint, even
+/// if they were compressed with different coding options.
+pub fn fingerprint_compressed<R>(mut from: R) -> Result<[u8; 32], DecompressionError>
+where
+    R: Read,
+{
+    let header = read_header(&mut from)?;
+    let image = decompress_image_with_header(from, &header)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(header.width.to_be_bytes());
+    hasher.update(header.height.to_be_bytes());
+    hasher.update([header.color_type as u8, header.pixel_depth as u8]);
+    match &image {
+        DynamicImage::ImageLuma16(image) => hash_samples16(&mut hasher, image.as_raw()),
+        DynamicImage::ImageRgb16(image) => hash_samples16(&mut hasher, image.as_raw()),
+        image => hasher.update(image.as_bytes()),
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Hashes the 16-bit samples in big-endian, so that the fingerprint does
+/// not depend on the host endianness.
+fn hash_samples16(hasher: &mut Sha256, samples: &[u16]) {
+    for sample in samples {
+        hasher.update(sample.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fingerprint_compressed;
+    use crate::compression::{CodingOptions, CompressDecompress, FirstRowMode};
+    use image::{GrayImage, ImageBuffer, Luma};
+    use std::io::Cursor;
+
+    fn fingerprint<T: CompressDecompress>(image: T) -> [u8; 32] {
+        let mut sink = Vec::new();
+        image.compress(&mut sink).unwrap();
+        fingerprint_compressed(Cursor::new(sink)).unwrap()
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_coding_options() {
+        let image = GrayImage::from_fn(31, 17, |x, y| Luma([(x * y % 251) as u8]));
+
+        let mut default = Vec::new();
+        image.compress(&mut default).unwrap();
+
+        let options = CodingOptions {
+            run_length_encoding: true,
+            periodic_count_scaling: Some(64),
+            first_row_mode: FirstRowMode::Delta,
+            band_height: Some(4),
+            ..CodingOptions::default_for::<u8>()
+        };
+        let mut custom = Vec::new();
+        image.compress_with_options(&mut custom, options).unwrap();
+        assert_ne!(default, custom);
+
+        assert_eq!(
+            fingerprint_compressed(Cursor::new(default)).unwrap(),
+            fingerprint_compressed(Cursor::new(custom)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_depends_on_pixels() {
+        let mut image: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::new(8, 8);
+        let mut first = Vec::new();
+        image.compress(&mut first).unwrap();
+
+        image.put_pixel(3, 5, Luma([1000]));
+        let mut second = Vec::new();
+        image.compress(&mut second).unwrap();
+
+        assert_ne!(
+            fingerprint_compressed(Cursor::new(first)).unwrap(),
+            fingerprint_compressed(Cursor::new(second)).unwrap()
+        );
+    }
+
+    // The same samples in a transposed image are another image.
+    #[test]
+    fn test_fingerprint_depends_on_dimensions() {
+        let samples: Vec<u8> = (0..8).collect();
+        let wide = GrayImage::from_raw(4, 2, samples.clone()).unwrap();
+        let tall = GrayImage::from_raw(2, 4, samples).unwrap();
+        assert_ne!(fingerprint(wide), fingerprint(tall));
+    }
+
+    // The 16-bit sample 1 is hashed as the bytes [0, 1].
+    #[test]
+    fn test_fingerprint_depends_on_pixel_depth() {
+        let eight = GrayImage::from_raw(2, 1, vec![0, 1]).unwrap();
+        let sixteen: ImageBuffer<Luma<u16>, Vec<u16>> =
+            ImageBuffer::from_raw(1, 1, vec![1]).unwrap();
+        assert_ne!(fingerprint(eight), fingerprint(sixteen));
+    }
+}
@@ -1,15 +1,61 @@
+//! The felics file header.
+//!
+//! The header has the following layout:
+//!
+//! | Offset | Size | Field                          |
+//! |--------|------|--------------------------------|
+//! | 0      | 4    | The signature: `FLCS`          |
+//! | 4      | 1    | The color type                 |
+//! | 5      | 1    | The pixel depth                |
+//! | 6      | 4    | The width of the image         |
+//! | 10     | 4    | The height of the image        |
+//! | 14     | 1    | The format version             |
+//! | 15     | 3    | The order of the RGB channels  |
+//! | 18     | 1    | Run-length encoding (0 or 1)   |
+//! | 19     | 1    | The effective bit depth, or 0  |
+//! | 20     | 4    | Count scaling threshold, or 0  |
+//! | 24     | 1    | The first row mode             |
+//! | 25     | 4    | Phase-in rotation bias, signed |
+//! | 29     | 4    | The height of the bands, or 0  |
+//! | 33     | 1    | The quality preset, or 0       |
+//! | 34     | 1    | The chroma subsampling mode    |
+//!
+//! All multi-byte fields are big-endian, regardless of the host endianness.
+//! The fields after the format version depend on the version.
+
 use super::error::DecompressionError;
+use super::options::{
+    is_valid_bit_depth, is_valid_channel_order, ChromaSubsampling, EffectiveBitDepth, FirstRowMode,
+    Quality,
+};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::convert::TryFrom;
+use std::fmt;
 use std::io::{self, Read, Write};
 
+/// The version of the format written by `write_header`.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// The number of bytes written by `write_header`.
+pub(crate) const HEADER_SIZE: usize = 35;
+
 /// Supported color types by the felics compression algorithm.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ColorType {
     Gray = 0,
     Rgb = 1,
 }
 
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorType::Gray => write!(f, "Gray"),
+            ColorType::Rgb => write!(f, "RGB"),
+        }
+    }
+}
+
 impl TryFrom<u8> for ColorType {
     type Error = DecompressionError;
 
@@ -23,12 +69,29 @@ impl TryFrom<u8> for ColorType {
 }
 
 /// Supported pixel depths by the felics compression algorithm.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum PixelDepth {
     Eight = 0,
     Sixteen = 1,
 }
 
+impl PixelDepth {
+    /// Returns the number of bits of a pixel with this depth.
+    pub fn bits(&self) -> u8 {
+        match self {
+            PixelDepth::Eight => 8,
+            PixelDepth::Sixteen => 16,
+        }
+    }
+}
+
+impl fmt::Display for PixelDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-bit", self.bits())
+    }
+}
+
 impl TryFrom<u8> for PixelDepth {
     type Error = DecompressionError;
 
@@ -41,13 +104,34 @@ impl TryFrom<u8> for PixelDepth {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Header {
     pub color_type: ColorType,
     pub pixel_depth: PixelDepth,
     pub width: u32,
     pub height: u32,
+    /// The order in which the Y, Co and Cg channels were compressed.
+    pub channel_order: [u8; 3],
+    /// Whether runs of constant pixels are encoded using their length.
+    pub run_length_encoding: bool,
+    /// The number of bits used by the pixels, if less than the pixel depth.
+    pub effective_bit_depth: Option<EffectiveBitDepth>,
+    /// The threshold at which the k estimator halves its counts.
+    pub periodic_count_scaling: Option<u32>,
+    /// How the pixels of the first row were coded.
+    pub first_row_mode: FirstRowMode,
+    /// The shift of the in-range values before phase-in coding.
+    pub phase_in_rotation_bias: i32,
+    /// The height of the bands that were compressed independently.
+    pub band_height: Option<u32>,
+    /// The quality preset that the coding options were created from.
+    pub quality: Option<Quality>,
+    /// Whether the chroma channels were downsampled.
+    pub chroma_subsampling: ChromaSubsampling,
 }
 
+/// Writes the header to the given `Write`.
 pub fn write_header<T>(header: Header, mut to: T) -> io::Result<()>
 where
     T: Write,
@@ -57,9 +141,23 @@ where
     to.write_u8(header.pixel_depth as u8)?;
     to.write_u32::<BigEndian>(header.width)?;
     to.write_u32::<BigEndian>(header.height)?;
+    to.write_u8(FORMAT_VERSION)?;
+    to.write_all(&header.channel_order)?;
+    to.write_u8(header.run_length_encoding as u8)?;
+    to.write_u8(header.effective_bit_depth.map_or(0, |depth| depth.0))?;
+    to.write_u32::<BigEndian>(header.periodic_count_scaling.unwrap_or(0))?;
+    to.write_u8(header.first_row_mode as u8)?;
+    to.write_i32::<BigEndian>(header.phase_in_rotation_bias)?;
+    to.write_u32::<BigEndian>(header.band_height.unwrap_or(0))?;
+    to.write_u8(header.quality.map_or(0, |quality| quality as u8))?;
+    to.write_u8(header.chroma_subsampling as u8)?;
     Ok(())
 }
 
+/// Reads a header from the given `Read`.
+///
+/// A truncated header results in a `DecompressionError::IoError` of kind
+/// `io::ErrorKind::UnexpectedEof`.
 pub fn read_header<T>(mut from: T) -> Result<Header, DecompressionError>
 where
     T: Read,
@@ -75,10 +173,307 @@ where
     let width = from.read_u32::<BigEndian>()?;
     let height = from.read_u32::<BigEndian>()?;
 
+    let version = from.read_u8()?;
+    if version != FORMAT_VERSION {
+        return Err(DecompressionError::UnsupportedVersion(version));
+    }
+
+    let mut channel_order = [0; 3];
+    from.read_exact(&mut channel_order)?;
+    if !is_valid_channel_order(channel_order) {
+        return Err(DecompressionError::InvalidChannelOrder);
+    }
+
+    let run_length_encoding = match from.read_u8()? {
+        0 => false,
+        1 => true,
+        _ => return Err(DecompressionError::InvalidFlag),
+    };
+
+    let effective_bit_depth = match from.read_u8()? {
+        0 => None,
+        bits => Some(EffectiveBitDepth(bits)),
+    };
+    if let Some(depth) = effective_bit_depth {
+        if !is_valid_bit_depth(depth, &pixel_depth) {
+            return Err(DecompressionError::InvalidPixelDepth);
+        }
+    }
+
+    let periodic_count_scaling = match from.read_u32::<BigEndian>()? {
+        0 => None,
+        threshold => Some(threshold),
+    };
+
+    let first_row_mode = from.read_u8()?.try_into()?;
+    let phase_in_rotation_bias = from.read_i32::<BigEndian>()?;
+    let band_height = match from.read_u32::<BigEndian>()? {
+        0 => None,
+        height => Some(height),
+    };
+    let quality = match from.read_u8()? {
+        0 => None,
+        quality => Some(quality.try_into()?),
+    };
+    let chroma_subsampling = from.read_u8()?.try_into()?;
+
     Ok(Header {
         color_type,
         pixel_depth,
         width,
         height,
+        channel_order,
+        run_length_encoding,
+        effective_bit_depth,
+        periodic_count_scaling,
+        first_row_mode,
+        phase_in_rotation_bias,
+        band_height,
+        quality,
+        chroma_subsampling,
     })
 }
+
+/// The number of bytes of an xor checksum.
+pub const XOR_CHECKSUM_SIZE: usize = 8;
+
+/// Computes a cheap checksum of the given bytes, by xor-folding them into a
+/// `u64`, 8 bytes at a time, in big-endian order. The last chunk is padded
+/// with zeros.
+///
+/// This is much weaker than a CRC: it detects any single corrupted byte, but
+/// not swapped chunks or corruptions that cancel out.
+pub fn compute_xor_checksum(data: &[u8]) -> u64 {
+    data.chunks(XOR_CHECKSUM_SIZE).fold(0, |checksum, chunk| {
+        let mut bytes = [0; XOR_CHECKSUM_SIZE];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        checksum ^ u64::from_be_bytes(bytes)
+    })
+}
+
+/// Checks that the given bytes end with the big-endian xor checksum of the
+/// bytes that come before it.
+pub fn verify_xor_checksum(data: &[u8]) -> bool {
+    if data.len() < XOR_CHECKSUM_SIZE {
+        return false;
+    }
+    let (data, checksum) = data.split_at(data.len() - XOR_CHECKSUM_SIZE);
+    compute_xor_checksum(data).to_be_bytes() == checksum
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Returns the header of a 640x480 grayscale image with no optional
+    /// fields, which the tests adjust.
+    fn test_header() -> Header {
+        Header {
+            color_type: ColorType::Gray,
+            pixel_depth: PixelDepth::Eight,
+            width: 640,
+            height: 480,
+            channel_order: [0, 1, 2],
+            run_length_encoding: false,
+            effective_bit_depth: None,
+            periodic_count_scaling: None,
+            first_row_mode: FirstRowMode::Horizontal,
+            phase_in_rotation_bias: 0,
+            band_height: None,
+            quality: None,
+            chroma_subsampling: ChromaSubsampling::None,
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ColorType::Gray.to_string(), "Gray");
+        assert_eq!(ColorType::Rgb.to_string(), "RGB");
+        assert_eq!(PixelDepth::Eight.to_string(), "8-bit");
+        assert_eq!(PixelDepth::Sixteen.to_string(), "16-bit");
+    }
+
+    #[test]
+    fn test_try_from_exhaustive() {
+        for value in 0..=u8::MAX {
+            match (value, PixelDepth::try_from(value)) {
+                (0, Ok(PixelDepth::Eight)) | (1, Ok(PixelDepth::Sixteen)) => (),
+                (2.., Err(DecompressionError::InvalidPixelDepth)) => (),
+                (value, other) => panic!("Unexpected pixel depth for {}: {:?}", value, other),
+            }
+            match (value, ColorType::try_from(value)) {
+                (0, Ok(ColorType::Gray)) | (1, Ok(ColorType::Rgb)) => (),
+                (2.., Err(DecompressionError::InvalidColorType)) => (),
+                (value, other) => panic!("Unexpected color type for {}: {:?}", value, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_xor_checksum() {
+        assert_eq!(compute_xor_checksum(&[]), 0);
+        assert_eq!(
+            compute_xor_checksum(&[1, 2, 3, 4, 5, 6, 7, 8, 0xFF]),
+            0xFE02030405060708
+        );
+
+        let mut data: Vec<u8> = (0..100).collect();
+        let checksum = compute_xor_checksum(&data);
+        data.extend_from_slice(&checksum.to_be_bytes());
+        assert!(verify_xor_checksum(&data));
+
+        data[42] ^= 0x10;
+        assert!(!verify_xor_checksum(&data));
+
+        assert!(verify_xor_checksum(&[0; 8]));
+        assert!(!verify_xor_checksum(&[0; 7]));
+    }
+
+    // Every field differs from `test_header`, so that a swapped field is caught.
+    #[test]
+    fn test_header_round_trip() {
+        let header = Header {
+            color_type: ColorType::Rgb,
+            pixel_depth: PixelDepth::Sixteen,
+            width: 1920,
+            height: 1080,
+            channel_order: [1, 2, 0],
+            run_length_encoding: true,
+            effective_bit_depth: Some(EffectiveBitDepth(10)),
+            periodic_count_scaling: Some(512),
+            first_row_mode: FirstRowMode::Delta,
+            phase_in_rotation_bias: -7,
+            band_height: Some(64),
+            quality: Some(Quality::Fast),
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+        };
+        let mut bytes = Vec::new();
+        write_header(header.clone(), &mut bytes).unwrap();
+        assert_eq!(read_header(Cursor::new(&bytes)).unwrap(), header);
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let header = test_header();
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+        bytes[..4].copy_from_slice(b"FLCZ");
+
+        assert!(matches!(
+            read_header(Cursor::new(&bytes)),
+            Err(DecompressionError::InvalidSignature)
+        ));
+    }
+
+    // Every field has a distinct value, so that a misplaced field is caught.
+    #[test]
+    fn test_header_byte_layout() {
+        let header = Header {
+            color_type: ColorType::Rgb,
+            pixel_depth: PixelDepth::Sixteen,
+            width: 0x01020304,
+            height: 0x0A0B0C0D,
+            channel_order: [2, 0, 1],
+            run_length_encoding: true,
+            effective_bit_depth: Some(EffectiveBitDepth(12)),
+            periodic_count_scaling: Some(0x11223344),
+            first_row_mode: FirstRowMode::Delta,
+            phase_in_rotation_bias: -2,
+            band_height: Some(0x00000010),
+            quality: Some(Quality::Best),
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+        };
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+
+        assert_eq!(
+            bytes,
+            [
+                b'F', b'L', b'C', b'S', 0x01, 0x01, 0x01, 0x02, 0x03, 0x04, 0x0A, 0x0B, 0x0C, 0x0D,
+                1, 2, 0, 1, 1, 12, 0x11, 0x22, 0x33, 0x44, 2, 0xFF, 0xFF, 0xFF, 0xFE, 0x00, 0x00,
+                0x00, 0x10, 3, 1,
+            ]
+        );
+        assert_eq!(bytes.len(), HEADER_SIZE);
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        let header = test_header();
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+
+        for length in 0..bytes.len() {
+            match read_header(Cursor::new(&bytes[..length])) {
+                Err(DecompressionError::IoError(e)) => {
+                    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+        assert!(read_header(Cursor::new(&bytes)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_channel_order() {
+        let header = Header {
+            color_type: ColorType::Rgb,
+            channel_order: [0, 2, 2],
+            ..test_header()
+        };
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+
+        assert!(matches!(
+            read_header(Cursor::new(&bytes)),
+            Err(DecompressionError::InvalidChannelOrder)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let header = test_header();
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+        bytes[14] = 3;
+
+        assert!(matches!(
+            read_header(Cursor::new(&bytes)),
+            Err(DecompressionError::UnsupportedVersion(3))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_first_row_mode() {
+        let header = Header {
+            first_row_mode: FirstRowMode::Verbatim,
+            ..test_header()
+        };
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+        bytes[24] = 3;
+
+        assert!(matches!(
+            read_header(Cursor::new(&bytes)),
+            Err(DecompressionError::InvalidFirstRowMode)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_chroma_subsampling() {
+        let header = Header {
+            color_type: ColorType::Rgb,
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+            ..test_header()
+        };
+        let mut bytes = Vec::new();
+        write_header(header, &mut bytes).unwrap();
+        bytes[34] = 2;
+
+        assert!(matches!(
+            read_header(Cursor::new(&bytes)),
+            Err(DecompressionError::InvalidChromaSubsampling)
+        ));
+    }
+}
@@ -1,3 +1,6 @@
+use std::cmp;
+use std::io::{self, Write};
+
 /// Returns the two nearest neighbours of a pixel in a given image, that have already been visited
 /// in a raster scan.
 ///
@@ -23,11 +26,162 @@ pub fn nearest_neighbours(i: usize, width: usize) -> Option<(usize, usize)> {
     }
 }
 
+/// The previously visited neighbours of a pixel, see `nearest_neighbours_with_fallback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeighborhoodContext {
+    /// The two nearest neighbours, as returned by `nearest_neighbours`.
+    pub both: Option<(usize, usize)>,
+    /// The nearest neighbour, available whenever any neighbour was visited.
+    pub one: Option<usize>,
+}
+
+impl NeighborhoodContext {
+    /// Returns the two neighbours that induce the context of the pixel. A
+    /// single neighbour stands for both, which gives a zero context.
+    pub fn pair(&self) -> Option<(usize, usize)> {
+        self.both.or(self.one.map(|a| (a, a)))
+    }
+}
+
+/// Returns the nearest neighbours of a pixel like `nearest_neighbours`, with
+/// a single neighbour as a fallback when there are not two of them.
+///
+/// Only the first two pixels in raster-scan order have less than two
+/// neighbours: the first one has none, and the second one only has the first.
+pub fn nearest_neighbours_with_fallback(i: usize, width: usize) -> NeighborhoodContext {
+    with_fallback(i, nearest_neighbours(i, width))
+}
+
+/// Adds the single neighbour fallback to the two nearest neighbours of the
+/// pixel with the given index.
+fn with_fallback(i: usize, both: Option<(usize, usize)>) -> NeighborhoodContext {
+    let one = match both {
+        Some((a, _)) => Some(a),
+        None if i > 0 => Some(i - 1),
+        None => None,
+    };
+    NeighborhoodContext { both, one }
+}
+
+/// A pre-computed table of the `nearest_neighbours` of the pixels of an image.
+///
+/// From the third row on, the neighbours of a pixel are at the same distances
+/// behind it as in the third row, so only the first three rows are stored, and
+/// the table uses `3 * width * size_of::<Option<(usize, usize)>>()` bytes at most.
+pub struct NearestNeighbourLookup {
+    width: usize,
+    // The distances from the pixels of the first three rows to their neighbours.
+    distances: Vec<Option<(usize, usize)>>,
+}
+
+impl NearestNeighbourLookup {
+    /// Computes the nearest neighbours of the pixels of an image of the given size.
+    pub fn new(width: usize, height: usize) -> NearestNeighbourLookup {
+        let distances = (0..cmp::min(height, 3) * width)
+            .map(|i| nearest_neighbours(i, width).map(|(a, b)| (i - a, i - b)))
+            .collect();
+        NearestNeighbourLookup { width, distances }
+    }
+
+    /// Returns the two nearest neighbours of the pixel at `(x, y)`.
+    /// See `nearest_neighbours`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` is outside the image.
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let i = y * self.width + x;
+        let (a, b) = self.distances[cmp::min(y, 2) * self.width + x]?;
+        Some((i - a, i - b))
+    }
+
+    /// Returns the neighbours of the pixel at `(x, y)`, with a single
+    /// neighbour as a fallback. See `nearest_neighbours_with_fallback`.
+    #[inline]
+    pub fn get_with_fallback(&self, x: usize, y: usize) -> NeighborhoodContext {
+        with_fallback(y * self.width + x, self.get(x, y))
+    }
+}
+
+/// Visits the rows from top to bottom, each row from left to right.
+pub struct RasterScan {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+}
+
+impl RasterScan {
+    /// Returns an iterator over the coordinates of an image of the given size.
+    pub fn new(width: u32, height: u32) -> RasterScan {
+        RasterScan {
+            width,
+            height,
+            x: 0,
+            y: 0,
+        }
+    }
+}
+
+impl Iterator for RasterScan {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<(u32, u32)> {
+        if self.width == 0 || self.y == self.height {
+            return None;
+        }
+        let point = (self.x, self.y);
+        self.x += 1;
+        if self.x == self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+        Some(point)
+    }
+}
+
+/// A `Write` that counts the bytes that were written to the inner `Write`.
+pub struct CountingWriter<W> {
+    inner: W,
+    count: usize,
+}
+
+impl<W> CountingWriter<W> {
+    pub fn new(inner: W) -> CountingWriter<W> {
+        CountingWriter { inner, count: 0 }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl<W> Write for CountingWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::nearest_neighbours;
+    use super::{
+        nearest_neighbours, nearest_neighbours_with_fallback, CountingWriter,
+        NearestNeighbourLookup, NeighborhoodContext, RasterScan,
+    };
+    use std::io::Write;
     pub fn pti((x, y): (usize, usize), width: usize) -> usize {
-        return y * width + x;
+        y * width + x
     }
     #[test]
     fn test_nearest_neighbours() {
@@ -67,4 +221,81 @@ mod test {
             Some((pti((0, 1), width), pti((0, 0), width)))
         );
     }
+
+    #[test]
+    fn test_nearest_neighbours_with_fallback() {
+        for width in [1, 2, 23] {
+            assert_eq!(
+                nearest_neighbours_with_fallback(0, width),
+                NeighborhoodContext {
+                    both: None,
+                    one: None
+                }
+            );
+            assert_eq!(
+                nearest_neighbours_with_fallback(1, width),
+                NeighborhoodContext {
+                    both: None,
+                    one: Some(0)
+                }
+            );
+            for i in 2..width * 3 {
+                let context = nearest_neighbours_with_fallback(i, width);
+                let both = nearest_neighbours(i, width);
+                assert!(both.is_some());
+                assert_eq!(context.both, both);
+                assert_eq!(context.one, both.map(|(a, _)| a));
+                assert_eq!(context.pair(), both);
+            }
+            assert_eq!(nearest_neighbours_with_fallback(0, width).pair(), None);
+            assert_eq!(
+                nearest_neighbours_with_fallback(1, width).pair(),
+                Some((0, 0))
+            );
+        }
+    }
+
+    #[test]
+    fn test_nearest_neighbour_lookup() {
+        for (width, height) in [(23, 10), (1, 5), (5, 1), (2, 2), (3, 2), (0, 4)] {
+            let lookup = NearestNeighbourLookup::new(width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    let i = pti((x, y), width);
+                    assert_eq!(lookup.get(x, y), nearest_neighbours(i, width));
+                    assert_eq!(
+                        lookup.get_with_fallback(x, y),
+                        nearest_neighbours_with_fallback(i, width)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_raster_scan_empty() {
+        assert_eq!(RasterScan::new(0, 3).count(), 0);
+        assert_eq!(RasterScan::new(3, 0).count(), 0);
+    }
+
+    #[test]
+    fn test_raster_scan() {
+        let (width, height) = (13, 5);
+        for (i, (x, y)) in RasterScan::new(width, height).enumerate() {
+            assert_eq!(pti((x as usize, y as usize), width as usize), i);
+        }
+    }
+
+    #[test]
+    fn test_counting_writer() {
+        let mut sink = Vec::new();
+        let mut writer = CountingWriter::new(&mut sink);
+        assert_eq!(writer.count(), 0);
+
+        writer.write_all(&[1, 2, 3]).unwrap();
+        writer.write_all(&[]).unwrap();
+        writer.write_all(&[4, 5]).unwrap();
+        assert_eq!(writer.count(), 5);
+        assert_eq!(sink, [1, 2, 3, 4, 5]);
+    }
 }
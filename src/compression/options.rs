@@ -0,0 +1,468 @@
+use super::error::DecompressionError;
+use super::format::{Header, PixelDepth};
+use super::traits::Intensity;
+use std::{cmp, io};
+
+/// The number of bits that are actually used by the pixels of an image.
+///
+/// This is useful for images whose pixels are stored in a bigger container,
+/// like 10-bit or 12-bit images stored as `u16`. The coder then sizes its
+/// tables for the actual range of the pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct EffectiveBitDepth(pub u8);
+
+impl EffectiveBitDepth {
+    /// Returns the maximum pixel value that fits in this bit depth.
+    pub fn max_value(&self) -> u32 {
+        (1u32 << self.0) - 1
+    }
+}
+
+/// How the pixels of the first row are coded, as there are no pixels above them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum FirstRowMode {
+    /// Code the pixels like the rest of the image, using the two previous
+    /// pixels of the row as the nearest neighbours.
+    #[default]
+    Horizontal = 0,
+    /// Store the pixels uncompressed.
+    Verbatim = 1,
+    /// Code the difference between each pixel and the previous one using
+    /// adaptive rice coding.
+    Delta = 2,
+}
+
+impl TryFrom<u8> for FirstRowMode {
+    type Error = DecompressionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FirstRowMode::Horizontal),
+            1 => Ok(FirstRowMode::Verbatim),
+            2 => Ok(FirstRowMode::Delta),
+            _ => Err(DecompressionError::InvalidFirstRowMode),
+        }
+    }
+}
+
+/// Whether the chroma channels of an RGB image are downsampled before they
+/// are compressed, which makes the compression lossy.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ChromaSubsampling {
+    /// Compress the Co and Cg channels at full resolution, losslessly.
+    #[default]
+    None = 0,
+    /// Halve the width and height of the Co and Cg channels by averaging
+    /// blocks of 2x2 pixels, and upsample them bilinearly after they are
+    /// decompressed.
+    Yuv420 = 1,
+}
+
+impl TryFrom<u8> for ChromaSubsampling {
+    type Error = DecompressionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ChromaSubsampling::None),
+            1 => Ok(ChromaSubsampling::Yuv420),
+            _ => Err(DecompressionError::InvalidChromaSubsampling),
+        }
+    }
+}
+
+/// A preset of coding options, trading compression speed for size.
+///
+/// The quality also selects the k values of the estimator, which are not
+/// stored in the header, so it is recorded in the header instead.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Quality {
+    /// Only tries the even k values of the pixel intensity type, and stores
+    /// the first row uncompressed.
+    Fast = 1,
+    /// The default coding options.
+    #[default]
+    Balanced = 2,
+    /// Tries every k value from the smallest one of the pixel intensity type
+    /// up to one past its largest one, codes the first row using deltas, and
+    /// halves the counts of the k estimator twice as often, so that it
+    /// adapts faster.
+    Best = 3,
+}
+
+/// Every k value supported by the rice coder.
+const ALL_K_VALUES: [u8; 32] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+    26, 27, 28, 29, 30, 31,
+];
+
+/// The even k values supported by the rice coder.
+const EVEN_K_VALUES: [u8; 16] = [0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30];
+
+impl Quality {
+    /// Returns the k values used by this preset, given the default k values
+    /// of the pixel intensity type.
+    fn k_values(&self, default: &'static [u8]) -> &'static [u8] {
+        let (Some(&min), Some(&max)) = (default.first(), default.last()) else {
+            return default;
+        };
+        let (min, max) = (min as usize, max as usize);
+        let k_values: &'static [u8] = match self {
+            Quality::Fast => &EVEN_K_VALUES[min.div_ceil(2)..=max / 2],
+            Quality::Balanced => default,
+            Quality::Best => &ALL_K_VALUES[min..=cmp::min(max + 1, 31)],
+        };
+        // A single odd k value has no even k values around it.
+        if k_values.is_empty() {
+            return default;
+        }
+        k_values
+    }
+}
+
+impl TryFrom<u8> for Quality {
+    type Error = DecompressionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Quality::Fast),
+            2 => Ok(Quality::Balanced),
+            3 => Ok(Quality::Best),
+            _ => Err(DecompressionError::InvalidFlag),
+        }
+    }
+}
+
+/// When the compressor flushes the underlying writer.
+///
+/// Whole bytes are always passed to the writer as soon as they are coded, so
+/// this only controls the calls to `Write::flush`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct OutputBufferMode {
+    /// Flush the writer after each channel is compressed.
+    pub channel_flush: bool,
+    /// Flush the writer once the image is compressed. Callers that disable
+    /// this must flush the writer themselves.
+    pub final_flush: bool,
+}
+
+impl Default for OutputBufferMode {
+    fn default() -> OutputBufferMode {
+        OutputBufferMode {
+            channel_flush: false,
+            final_flush: true,
+        }
+    }
+}
+
+/// The parameters used by the felics coder to compress an image.
+///
+/// The options are created for a given pixel intensity type using
+/// `CodingOptions::default_for`, and the public fields can then be adjusted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CodingOptions {
+    pub(crate) max_context: u32,
+    pub(crate) k_values: &'static [u8],
+    /// The order in which the channels of an RGB image are compressed.
+    /// The entries are the indices of the Y, Co and Cg channels: `0`, `1` and `2`.
+    ///
+    /// This has no effect on grayscale images.
+    pub channel_order: [u8; 3],
+    /// Encode runs of pixels that are equal to both of their nearest
+    /// neighbours using their length, instead of coding each pixel.
+    ///
+    /// This greatly improves the compression of images with large constant
+    /// regions, but is slightly worse for natural images, so it is disabled
+    /// by default.
+    pub run_length_encoding: bool,
+    /// The number of bits used by the pixels, if less than the size of the
+    /// pixel intensity type. `None` means that all the bits are used.
+    pub effective_bit_depth: Option<EffectiveBitDepth>,
+    /// The count at which the k estimator halves the code lengths of a
+    /// context, so that it adapts to local statistics. `None` disables the
+    /// scaling.
+    ///
+    /// Defaults to `Intensity::COUNT_SCALING`. Higher thresholds may suit
+    /// large images, where the statistics take longer to stabilize.
+    pub periodic_count_scaling: Option<u32>,
+    /// How the pixels of the first row are coded.
+    pub first_row_mode: FirstRowMode,
+    /// Shifts the in-range values before they are phase-in coded, by at most
+    /// the width of the `[L, H]` interval.
+    ///
+    /// Phase-in codes give the shortest codewords to the middle of the
+    /// interval, so a positive bias moves them towards `L`, and a negative
+    /// bias towards `H`. A bias of 0 keeps them in the middle.
+    pub phase_in_rotation_bias: i32,
+    /// Splits the image into horizontal bands of this many rows, and
+    /// compresses each band independently, with fresh parameter estimates.
+    /// `None` compresses the image as a single band.
+    ///
+    /// Independent bands adapt faster to images whose statistics change from
+    /// top to bottom, at the cost of relearning the parameters for each band.
+    pub band_height: Option<u32>,
+    /// The quality preset that the options were created from, which is
+    /// recorded in the header. It selects the k values of the estimator,
+    /// see `Quality`.
+    pub quality: Option<Quality>,
+    /// When the writer is flushed. It is not recorded in the header, as it
+    /// has no effect on the compressed data.
+    ///
+    /// Suppressing the flushes avoids unneeded system calls on slow writers
+    /// that are buffered by the caller.
+    pub output_buffer_mode: OutputBufferMode,
+    /// Whether the Co and Cg channels of an RGB image are downsampled.
+    ///
+    /// Subsampling trades a small color error for a smaller file, and is
+    /// disabled by default, as felics is lossless. This has no effect on
+    /// grayscale images.
+    pub chroma_subsampling: ChromaSubsampling,
+}
+
+impl CodingOptions {
+    /// Creates the default coding options for the given pixel intensity type.
+    pub fn default_for<T>() -> CodingOptions
+    where
+        T: Intensity,
+    {
+        CodingOptions {
+            max_context: T::MAX_CONTEXT,
+            k_values: T::K_VALUES,
+            periodic_count_scaling: T::COUNT_SCALING,
+            channel_order: [0, 1, 2],
+            run_length_encoding: false,
+            effective_bit_depth: None,
+            first_row_mode: FirstRowMode::Horizontal,
+            phase_in_rotation_bias: 0,
+            band_height: None,
+            quality: None,
+            output_buffer_mode: OutputBufferMode::default(),
+            chroma_subsampling: ChromaSubsampling::None,
+        }
+    }
+
+    /// Creates the coding options of a quality preset for the given pixel
+    /// intensity type.
+    pub fn for_quality<T>(quality: Quality) -> CodingOptions
+    where
+        T: Intensity,
+    {
+        let default = CodingOptions {
+            quality: Some(quality),
+            ..CodingOptions::default_for::<T>()
+        };
+        match quality {
+            Quality::Fast => CodingOptions {
+                first_row_mode: FirstRowMode::Verbatim,
+                ..default
+            },
+            Quality::Balanced => default,
+            Quality::Best => CodingOptions {
+                periodic_count_scaling: T::COUNT_SCALING.map(|threshold| threshold / 2),
+                first_row_mode: FirstRowMode::Delta,
+                ..default
+            },
+        }
+    }
+
+    /// Creates the coding options that were used to compress an image
+    /// with the given header.
+    pub(crate) fn from_header<T>(header: &Header) -> CodingOptions
+    where
+        T: Intensity,
+    {
+        CodingOptions {
+            channel_order: header.channel_order,
+            run_length_encoding: header.run_length_encoding,
+            effective_bit_depth: header.effective_bit_depth,
+            periodic_count_scaling: header.periodic_count_scaling,
+            first_row_mode: header.first_row_mode,
+            phase_in_rotation_bias: header.phase_in_rotation_bias,
+            band_height: header.band_height,
+            quality: header.quality,
+            chroma_subsampling: header.chroma_subsampling,
+            ..CodingOptions::default_for::<T>()
+        }
+    }
+
+    /// Returns the maximum context, as limited by the effective bit depth.
+    pub(crate) fn max_context(&self) -> u32 {
+        match self.effective_bit_depth {
+            Some(depth) => depth.max_value() * 2,
+            None => self.max_context,
+        }
+    }
+
+    /// Returns the maximum value of a pixel, as limited by the effective bit depth.
+    pub(crate) fn max_value(&self) -> i32 {
+        (self.max_context() / 2) as i32
+    }
+
+    /// Returns the list of k values of the quality preset, without the ones
+    /// that are too big for the effective bit depth.
+    pub(crate) fn k_values(&self) -> &'static [u8] {
+        let k_values = match self.quality {
+            Some(quality) => quality.k_values(self.k_values),
+            None => self.k_values,
+        };
+        match self.effective_bit_depth {
+            Some(EffectiveBitDepth(bits)) => {
+                let count = k_values.iter().take_while(|&&k| k + 1 < bits).count();
+                &k_values[..count]
+            }
+            None => k_values,
+        }
+    }
+
+    /// Checks that the options can be used to compress the given pixels.
+    pub(crate) fn validate_for<T>(&self, pixels: &[T]) -> io::Result<()>
+    where
+        T: Intensity,
+    {
+        if self.max_context != T::MAX_CONTEXT || self.k_values != T::K_VALUES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The coding options were created for another pixel intensity type",
+            ));
+        }
+        if !is_valid_channel_order(self.channel_order) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The channel order is not a permutation of [0, 1, 2]",
+            ));
+        }
+        if self.periodic_count_scaling == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The count scaling threshold must be positive",
+            ));
+        }
+        if self.band_height == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The band height must be positive",
+            ));
+        }
+        if let Some(depth) = self.effective_bit_depth {
+            if !is_valid_bit_depth(depth, &T::PIXEL_DEPTH) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The effective bit depth does not fit the pixel depth",
+                ));
+            }
+            let max_value = depth.max_value() as i32;
+            if pixels.iter().any(|&x| x.into() > max_value) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "A pixel does not fit the effective bit depth",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the effective bit depth can be used for the given pixel depth.
+pub(crate) fn is_valid_bit_depth(depth: EffectiveBitDepth, pixel_depth: &PixelDepth) -> bool {
+    (2..=pixel_depth.bits()).contains(&depth.0)
+}
+
+/// Returns true if the channel order is a permutation of `[0, 1, 2]`.
+pub(crate) fn is_valid_channel_order(order: [u8; 3]) -> bool {
+    let mut sorted = order;
+    sorted.sort_unstable();
+    sorted == [0, 1, 2]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_channel_order_validation() {
+        assert!(is_valid_channel_order([0, 1, 2]));
+        assert!(is_valid_channel_order([2, 0, 1]));
+        assert!(!is_valid_channel_order([0, 0, 1]));
+        assert!(!is_valid_channel_order([0, 1, 3]));
+
+        let mut options = CodingOptions::default_for::<u8>();
+        assert!(options.validate_for::<u8>(&[]).is_ok());
+        assert!(options.validate_for::<u16>(&[]).is_err());
+
+        options.channel_order = [1, 1, 1];
+        assert!(options.validate_for::<u8>(&[]).is_err());
+
+        options.channel_order = [0, 1, 2];
+        options.band_height = Some(0);
+        assert!(options.validate_for::<u8>(&[]).is_err());
+    }
+
+    #[test]
+    fn test_quality_presets() {
+        let balanced = CodingOptions::for_quality::<u16>(Quality::Balanced);
+        assert_eq!(
+            balanced,
+            CodingOptions {
+                quality: Some(Quality::Balanced),
+                ..CodingOptions::default_for::<u16>()
+            }
+        );
+
+        assert_eq!(balanced.k_values(), u16::K_VALUES);
+
+        let fast = CodingOptions::for_quality::<u8>(Quality::Fast);
+        assert_eq!(fast.periodic_count_scaling, u8::COUNT_SCALING);
+        assert_eq!(fast.first_row_mode, FirstRowMode::Verbatim);
+        assert_eq!(fast.k_values(), &[0, 2, 4]);
+        let fast = CodingOptions::for_quality::<u16>(Quality::Fast);
+        assert_eq!(fast.k_values(), &[0, 2, 4, 6, 8, 10, 12, 14]);
+
+        let best = CodingOptions::for_quality::<u8>(Quality::Best);
+        assert_eq!(best.first_row_mode, FirstRowMode::Delta);
+        assert_eq!(best.periodic_count_scaling, Some(512));
+        assert_eq!(best.k_values(), &[0, 1, 2, 3, 4, 5, 6]);
+        let mut best = CodingOptions::for_quality::<u16>(Quality::Best);
+        assert_eq!(best.k_values(), &ALL_K_VALUES[..=15]);
+
+        // The effective bit depth still limits the k values of the preset.
+        best.effective_bit_depth = Some(EffectiveBitDepth(12));
+        assert_eq!(best.k_values(), &ALL_K_VALUES[..=10]);
+
+        // The presets keep the range of custom k values.
+        assert_eq!(Quality::Fast.k_values(&[3, 4, 5, 6, 7]), &[4, 6]);
+        assert_eq!(Quality::Fast.k_values(&[3]), &[3]);
+        assert_eq!(Quality::Best.k_values(&[3, 7, 31]), &ALL_K_VALUES[3..]);
+
+        for quality in [Quality::Fast, Quality::Balanced, Quality::Best] {
+            assert_eq!(Quality::try_from(quality as u8).unwrap(), quality);
+        }
+        assert!(Quality::try_from(0).is_err());
+    }
+
+    #[test]
+    fn test_effective_bit_depth() {
+        let mut options = CodingOptions::default_for::<u16>();
+        options.effective_bit_depth = Some(EffectiveBitDepth(12));
+
+        assert_eq!(options.max_context(), 4095 * 2);
+        assert_eq!(options.max_value(), 4095);
+        assert_eq!(options.k_values(), &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        assert!(options.validate_for::<u16>(&[0, 4095]).is_ok());
+        assert!(options.validate_for::<u16>(&[0, 4096]).is_err());
+
+        options.effective_bit_depth = Some(EffectiveBitDepth(17));
+        assert!(options.validate_for::<u16>(&[]).is_err());
+        options.effective_bit_depth = Some(EffectiveBitDepth(1));
+        assert!(options.validate_for::<u16>(&[]).is_err());
+
+        // The full bit depth uses the default parameters.
+        options.effective_bit_depth = Some(EffectiveBitDepth(16));
+        assert_eq!(options.max_context(), u16::MAX_CONTEXT);
+        assert_eq!(options.k_values(), u16::K_VALUES);
+    }
+}
@@ -1,15 +1,46 @@
-use crate::coding::rice_coding::RiceCoder;
+use super::traits::Intensity;
+use std::collections::HashMap;
+use std::{cmp, fmt};
 
 /// This struct is used to estimate the optimal Rice parameter
 /// value k from a given list of reasonable parameters for k.
 pub struct KEstimator {
     max_context: u32,
-    k_values: &'static [u8],
+    k_values: Vec<u8>,
     // context_map[C][k] - the code length we would have had
     // if we had used parameter k to encode all values encountered
     // so far in the context C.
-    context_map: Vec<Vec<u32>>,
+    context_map: Vec<Vec<u64>>,
     halve_at: Option<u32>,
+    // counts[C] - the number of values encoded in the context C
+    // and their sum, which are never scaled.
+    counts: Vec<(u64, u64)>,
+    // The contexts in which values were encoded, in the order of their first use.
+    used: Vec<u32>,
+    // frequencies[C][v] - how many times the value v was encoded in the
+    // context C, if entropy tracking is enabled.
+    frequencies: Option<HashMap<u32, HashMap<u32, u64>>>,
+}
+
+/// Statistics about the values that were encoded in a context.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContextStatistics {
+    /// The context these statistics are about.
+    pub context: u32,
+    /// The k value that is currently picked for this context.
+    pub best_k: u8,
+    /// The number of values that were encoded in this context.
+    pub count: u64,
+    /// The mean of the values that were encoded in this context.
+    pub mean: f64,
+    /// A first-order estimate of the entropy of the context, in bits per value.
+    ///
+    /// This is the entropy of a geometric distribution with the same mean,
+    /// the distribution that Rice codes are designed for.
+    pub entropy: f64,
+    /// The expected Rice code length using `best_k`, in bits per value, under
+    /// the same geometric distribution.
+    pub code_length: f64,
 }
 
 impl KEstimator {
@@ -32,63 +63,496 @@ impl KEstimator {
             context_map.push(k);
         }
 
-        return KEstimator {
+        KEstimator {
             max_context,
-            k_values,
+            k_values: k_values.to_vec(),
             context_map,
             halve_at,
-        };
+            counts: vec![(0, 0); max_context as usize + 1],
+            used: Vec::new(),
+            frequencies: None,
+        }
     }
 
-    /// Updates the cumulative totals for this context
-    /// to reflect that we have encoded a new value.
+    /// Creates a new KEstimator for all the k parameters in `[k_min, k_max]`.
+    /// See `KEstimator::new`.
     ///
     /// # Panics
+    /// Panics if `k_min` is greater than `k_max`, or if `k_max` is greater
+    /// than 31.
+    pub fn new_with_k_range(
+        max_context: u32,
+        k_min: u8,
+        k_max: u8,
+        halve_at: Option<u32>,
+    ) -> KEstimator {
+        if k_min > k_max {
+            panic!("The range of k values is empty!");
+        }
+        if k_max > 31 {
+            panic!("k is too big!");
+        }
+        let k_values: Vec<u8> = (k_min..=k_max).collect();
+
+        KEstimator {
+            max_context,
+            context_map: vec![vec![0; k_values.len()]; max_context as usize + 1],
+            k_values,
+            halve_at,
+            counts: vec![(0, 0); max_context as usize + 1],
+            used: Vec::new(),
+            frequencies: None,
+        }
+    }
+
+    /// Makes the estimator count how many times each value is encoded in
+    /// every context, so that `context_entropy` can compute their exact
+    /// entropy. This costs a hash lookup for every update.
+    pub fn with_entropy_tracking(mut self) -> KEstimator {
+        self.frequencies = Some(HashMap::new());
+        self
+    }
+
+    /// Creates a new KEstimator using the default parameters
+    /// of the given pixel intensity type.
+    pub fn default_for<T>() -> KEstimator
+    where
+        T: Intensity,
+    {
+        KEstimator::new(T::MAX_CONTEXT, T::K_VALUES, T::COUNT_SCALING)
+    }
+
+    /// Updates the cumulative totals for this context
+    /// to reflect that we have encoded a new value.
     ///
-    /// Panics if context > max_context.
+    /// Contexts greater than max_context are clamped to max_context,
+    /// so that corrupted input cannot cause a panic.
     pub fn update(&mut self, context: u32, encoded: u32) {
-        assert!(context <= self.max_context);
-        let ks_for_context = &mut self.context_map[context as usize];
+        let context = self.clamp_context(context);
+        update_code_lengths(
+            &mut self.context_map[context as usize],
+            &self.k_values,
+            self.halve_at,
+            encoded,
+        );
 
-        for (ki, &k) in self.k_values.iter().enumerate() {
-            let code_length = RiceCoder::new(k).code_length(encoded);
-            ks_for_context[ki] += code_length;
+        let (count, sum) = &mut self.counts[context as usize];
+        if *count == 0 {
+            self.used.push(context);
         }
+        *count += 1;
+        *sum += encoded as u64;
 
-        if let Some(halve_at) = self.halve_at {
-            let min_value = ks_for_context.iter().min().unwrap();
-            if *min_value > halve_at {
-                ks_for_context.iter_mut().for_each(|x| *x /= 2);
-            }
+        if let Some(frequencies) = &mut self.frequencies {
+            *frequencies
+                .entry(context)
+                .or_default()
+                .entry(encoded)
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// Forgets all the values that were encoded, as if the estimator was
+    /// just created.
+    ///
+    /// Only the contexts that were used are cleared, so this is much cheaper
+    /// than creating a new estimator when most of the contexts are unused.
+    pub fn reset(&mut self) {
+        for context in self.used.drain(..) {
+            self.context_map[context as usize].fill(0);
+            self.counts[context as usize] = (0, 0);
+        }
+        if let Some(frequencies) = &mut self.frequencies {
+            frequencies.clear();
         }
     }
 
+    /// Returns the Shannon entropy of the values encoded in the given context,
+    /// in bits per value: `H = -sum(p * log2(p))`, where `p` is the frequency
+    /// of each value. It is 0 if no values were encoded in the context.
+    ///
+    /// Returns `None` if entropy tracking was not enabled using
+    /// `with_entropy_tracking`. Contexts greater than max_context are clamped
+    /// to max_context.
+    pub fn context_entropy(&self, context: u32) -> Option<f64> {
+        let context = self.clamp_context(context);
+        let frequencies = self.frequencies.as_ref()?;
+        let Some(frequencies) = frequencies.get(&context) else {
+            return Some(0.0);
+        };
+
+        let total = self.counts[context as usize].0 as f64;
+        let entropy = frequencies
+            .values()
+            .map(|&frequency| -xlog2x(frequency as f64 / total))
+            .sum();
+        Some(entropy)
+    }
+
     /// Returns the best parameter value k for the current context.
     ///
+    /// Contexts greater than max_context are clamped to max_context.
+    pub fn get_k(&self, context: u32) -> u8 {
+        let context = self.clamp_context(context);
+        let (best, _) = self.best_for_context(context as usize);
+        self.k_values[best]
+    }
+
+    /// Returns the k value that is optimal for a geometric distribution with
+    /// the mean of the values encoded in the given context, see
+    /// `optimal_k_for_mean`. Unlike `get_k`, this does not depend on the
+    /// order of the values, so it can be used to check whether the estimator
+    /// converged. If the optimal k is not one of the k values of the
+    /// estimator, the closest one is returned.
+    ///
+    /// Contexts greater than max_context are clamped to max_context.
+    pub fn optimal_k_for_context(&self, context: u32) -> u8 {
+        let context = self.clamp_context(context);
+        let (count, sum) = self.counts[context as usize];
+        let optimal = optimal_k_for_mean(sum as f64 / count as f64);
+
+        // Prefer the largest k on ties, like `get_k`.
+        *self
+            .k_values
+            .iter()
+            .rev()
+            .min_by_key(|&&k| k.abs_diff(optimal))
+            .unwrap()
+    }
+
+    fn clamp_context(&self, context: u32) -> u32 {
+        cmp::min(context, self.max_context)
+    }
+
+    /// Returns the statistics of all the contexts in which values were encoded,
+    /// sorted by context.
+    pub fn get_statistics(&self) -> Vec<ContextStatistics> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &(count, _))| count > 0)
+            .map(|(context, &(count, sum))| {
+                let (best, _) = self.best_for_context(context);
+                let best_k = self.k_values[best];
+                let mean = sum as f64 / count as f64;
+
+                // A geometric distribution over [0, inf) with mean `mean`
+                // has P(v) = (1 - q) * q^v, where q = mean / (1 + mean).
+                let entropy = (1.0 + mean) * (1.0 + mean).log2() - xlog2x(mean);
+                let q = mean / (1.0 + mean);
+                let q_m = q.powf(2f64.powi(best_k as i32));
+                let code_length = (best_k + 1) as f64 + q_m / (1.0 - q_m);
+
+                ContextStatistics {
+                    context: context as u32,
+                    best_k,
+                    count,
+                    mean,
+                    entropy,
+                    code_length,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the index of the best k value for the given context,
+    /// together with its code length.
+    fn best_for_context(&self, context: usize) -> (usize, u64) {
+        best_code_length(&self.context_map[context])
+    }
+}
+
+/// A `KEstimator` that only allocates the code lengths of a context
+/// when a value is first encoded in it.
+///
+/// This makes construction O(1), which helps when most of the contexts are
+/// never used, as is the case for `u16` images, at the cost of a hash lookup
+/// for every pixel.
+pub struct KEstimatorSparse {
+    max_context: u32,
+    k_values: &'static [u8],
+    // context_map[C][k] - the same as in KEstimator, for the
+    // contexts that were used.
+    context_map: HashMap<u32, Vec<u64>>,
+    halve_at: Option<u32>,
+}
+
+impl KEstimatorSparse {
+    /// Creates a new KEstimatorSparse for the given set
+    /// of k parameters. See `KEstimator::new`.
+    ///
     /// # Panics
+    /// Panics if the list of reasonable k values is empty.
+    pub fn new(
+        max_context: u32,
+        k_values: &'static [u8],
+        halve_at: Option<u32>,
+    ) -> KEstimatorSparse {
+        if k_values.is_empty() {
+            panic!("The list of k values is empty!");
+        }
+
+        KEstimatorSparse {
+            max_context,
+            k_values,
+            context_map: HashMap::new(),
+            halve_at,
+        }
+    }
+
+    /// Creates a new KEstimatorSparse using the default parameters
+    /// of the given pixel intensity type.
+    pub fn default_for<T>() -> KEstimatorSparse
+    where
+        T: Intensity,
+    {
+        KEstimatorSparse::new(T::MAX_CONTEXT, T::K_VALUES, T::COUNT_SCALING)
+    }
+
+    /// Updates the cumulative totals for this context
+    /// to reflect that we have encoded a new value.
+    ///
+    /// Contexts greater than max_context are clamped to max_context.
+    pub fn update(&mut self, context: u32, encoded: u32) {
+        let context = cmp::min(context, self.max_context);
+        let ks_for_context = self
+            .context_map
+            .entry(context)
+            .or_insert_with(|| vec![0; self.k_values.len()]);
+        update_code_lengths(ks_for_context, self.k_values, self.halve_at, encoded);
+    }
+
+    /// Returns the best parameter value k for the current context.
     ///
-    /// Panics if context > max_context.
+    /// Contexts greater than max_context are clamped to max_context.
     pub fn get_k(&self, context: u32) -> u8 {
-        assert!(context <= self.max_context);
-        let ks_for_context = &self.context_map[context as usize];
+        let context = cmp::min(context, self.max_context);
+        let best = match self.context_map.get(&context) {
+            Some(ks_for_context) => best_code_length(ks_for_context).0,
+            // All the code lengths of an unused context are 0.
+            None => self.k_values.len() - 1,
+        };
+        self.k_values[best]
+    }
+}
+
+/// A `KEstimator` whose contexts start with a prior belief, instead of
+/// treating all the k values as equally good.
+///
+/// Before any value is encoded, the values of a context C are assumed to be
+/// uniformly distributed in `[0, C]`, which is the range of the values in the
+/// context when the neighbours are uniformly distributed. The code lengths of
+/// each context start as if `prior` such values had already been encoded, and
+/// the encoded values then gradually outweigh the prior.
+pub struct BayesianKEstimator {
+    estimator: KEstimator,
+}
 
-        let mut smallest = u32::MAX;
-        let mut best = 0;
+impl BayesianKEstimator {
+    /// Creates a new BayesianKEstimator for the given set of k parameters,
+    /// starting with `prior` imaginary values in each context.
+    /// See `KEstimator::new`.
+    ///
+    /// A `prior` of 0 is the same as `KEstimator::new`.
+    ///
+    /// # Panics
+    /// Panics if the list of reasonable k values is empty.
+    pub fn new_with_prior(
+        max_context: u32,
+        k_values: &'static [u8],
+        prior: u32,
+        halve_at: Option<u32>,
+    ) -> BayesianKEstimator {
+        let mut estimator = KEstimator::new(max_context, k_values, halve_at);
 
-        for (i, k) in ks_for_context.iter().enumerate() {
-            if *k <= smallest {
-                best = i;
-                smallest = *k;
+        for (context, ks_for_context) in estimator.context_map.iter_mut().enumerate() {
+            for (ki, &k) in k_values.iter().enumerate() {
+                let mean = mean_uniform_code_length(context as u64, k);
+                ks_for_context[ki] = (prior as f64 * mean).round() as u64;
             }
         }
-        self.k_values[best]
+        BayesianKEstimator { estimator }
+    }
+
+    /// Updates the cumulative totals for this context
+    /// to reflect that we have encoded a new value.
+    ///
+    /// Contexts greater than max_context are clamped to max_context.
+    pub fn update(&mut self, context: u32, encoded: u32) {
+        self.estimator.update(context, encoded);
+    }
+
+    /// Returns the best parameter value k for the current context.
+    ///
+    /// Contexts greater than max_context are clamped to max_context.
+    pub fn get_k(&self, context: u32) -> u8 {
+        self.estimator.get_k(context)
+    }
+}
+
+/// Returns the mean rice code length with parameter k of the values that are
+/// uniformly distributed in `[0, max_value]`.
+fn mean_uniform_code_length(max_value: u64, k: u8) -> f64 {
+    // A rice code of v has `(v >> k) + 1 + k` bits. The values form
+    // `full` complete blocks of `2^k` values sharing the same `v >> k`,
+    // followed by `rest` values.
+    let count = max_value + 1;
+    let block = 1u64 << k;
+    let (full, rest) = (count / block, count % block);
+    let quotients = block * full * full.saturating_sub(1) / 2 + rest * full;
+    quotients as f64 / count as f64 + 1.0 + k as f64
+}
+
+/// Adds the code length of `encoded` to the code lengths of each k value,
+/// halving them when the smallest one reaches `halve_at`.
+///
+/// Without periodic count scaling the code lengths keep growing, so they
+/// saturate instead of overflowing.
+fn update_code_lengths(
+    ks_for_context: &mut [u64],
+    k_values: &[u8],
+    halve_at: Option<u32>,
+    encoded: u32,
+) {
+    for (ki, &k) in k_values.iter().enumerate() {
+        ks_for_context[ki] = ks_for_context[ki].saturating_add(rice_code_length(k, encoded));
+    }
+
+    if let Some(halve_at) = halve_at {
+        let min_value = ks_for_context.iter().min().unwrap();
+        if *min_value > halve_at as u64 {
+            ks_for_context.iter_mut().for_each(|x| *x /= 2);
+        }
+    }
+}
+
+/// Returns the length of the rice code of `encoded` for the parameter k.
+/// This is `RiceCoder::code_length` without constructing a `RiceCoder`,
+/// as it is called for every k value on each update.
+#[inline]
+fn rice_code_length(k: u8, encoded: u32) -> u64 {
+    (encoded >> k) as u64 + 1 + k as u64
+}
+
+/// Returns the index of the smallest code length, preferring the largest
+/// k value on ties, together with the code length.
+fn best_code_length(ks_for_context: &[u64]) -> (usize, u64) {
+    let mut smallest = u64::MAX;
+    let mut best = 0;
+
+    for (i, k) in ks_for_context.iter().enumerate() {
+        if *k <= smallest {
+            best = i;
+            smallest = *k;
+        }
+    }
+    (best, smallest)
+}
+
+/// Returns the rice parameter k that is optimal for values that follow a
+/// geometric distribution with the given mean.
+///
+/// With `q = mean / (1 + mean)`, the optimal k is
+/// `max(0, 1 + floor(log2(ln(φ - 1) / ln(q))))`, where φ is the golden ratio
+/// (Kiely, 2004). For large means, this is close to `log2(ln(2) * mean)`,
+/// but that approximation is off by one for small means.
+///
+/// This does not need any encoded values, so it can be used to pick a
+/// starting k for a context whose mean is known in advance.
+pub fn optimal_k_for_mean(mean: f64) -> u8 {
+    let golden_ratio = (1.0 + 5f64.sqrt()) / 2.0;
+    let q = mean / (1.0 + mean);
+    let k = 1.0 + ((golden_ratio - 1.0).ln() / q.ln()).log2().floor();
+    // Small and NaN means give 0.
+    k.clamp(0.0, 31.0) as u8
+}
+
+/// Returns `x * log2(x)`, which tends to 0 as `x` goes to 0.
+fn xlog2x(x: f64) -> f64 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x * x.log2()
+    }
+}
+
+/// The number of contexts shown by the `Debug` implementation of `KEstimator`.
+const DEBUG_CONTEXTS: usize = 10;
+
+/// Context maps bigger than this only show the contexts that were used.
+const DEBUG_SMALL_CONTEXT_MAP: usize = 256;
+
+/// The best k value of a context and its code length, as shown by `Debug`.
+struct ContextSummary {
+    best_k: u8,
+    code_length: u64,
+}
+
+impl fmt::Debug for ContextSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContextSummary")
+            .field("best_k", &self.best_k)
+            .field("code_length", &self.code_length)
+            .finish()
+    }
+}
+
+/// Shows the contexts with the longest code lengths, as they are the ones
+/// that were updated the most.
+struct TopContexts<'a>(&'a KEstimator);
+
+impl fmt::Debug for TopContexts<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let estimator = self.0;
+        let show_all = estimator.context_map.len() <= DEBUG_SMALL_CONTEXT_MAP;
+
+        let mut contexts: Vec<(usize, ContextSummary)> = (0..estimator.context_map.len())
+            .filter(|&context| show_all || estimator.context_map[context].iter().any(|&x| x > 0))
+            .map(|context| {
+                let (best, code_length) = estimator.best_for_context(context);
+                let summary = ContextSummary {
+                    best_k: estimator.k_values[best],
+                    code_length,
+                };
+                (context, summary)
+            })
+            .collect();
+
+        contexts.sort_by(|a, b| b.1.code_length.cmp(&a.1.code_length).then(a.0.cmp(&b.0)));
+        contexts.truncate(DEBUG_CONTEXTS);
+
+        f.debug_map()
+            .entries(contexts.iter().map(|(context, summary)| (context, summary)))
+            .finish()
+    }
+}
+
+impl fmt::Debug for KEstimator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let used_contexts = self
+            .context_map
+            .iter()
+            .filter(|ks| ks.iter().any(|&x| x > 0))
+            .count();
+
+        f.debug_struct("KEstimator")
+            .field("max_context", &self.max_context)
+            .field("k_values", &self.k_values)
+            .field("halve_at", &self.halve_at)
+            .field("used_contexts", &used_contexts)
+            .field("top_contexts", &TopContexts(self))
+            .finish()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::KEstimator;
+    use super::{
+        mean_uniform_code_length, optimal_k_for_mean, rice_code_length, BayesianKEstimator,
+        ContextStatistics, KEstimator, KEstimatorSparse,
+    };
     use crate::coding::rice_coding::RiceCoder;
+    use crate::compression::Intensity;
     use std::collections::HashMap;
 
     /// Check the corectnes of the context_map after some updates.
@@ -114,15 +578,28 @@ mod test {
         for (&context, values_in_context) in add_to_context.iter() {
             for (i, &k) in k_values.iter().enumerate() {
                 let coder = RiceCoder::new(k);
-                let total_length: u32 = values_in_context
+                let total_length: u64 = values_in_context
                     .iter()
-                    .map(|&value| coder.code_length(value))
+                    .map(|&value| coder.code_length(value) as u64)
                     .sum();
                 assert_eq!(total_length, estimator.context_map[context as usize][i]);
             }
         }
     }
 
+    /// Check that the inlined code length matches the one of the rice coder.
+    #[test]
+    fn test_rice_code_length() {
+        let values = [0, 1, 2, 3, 7, 8, 255, 256, 65535, 1 << 20, u32::MAX / 2];
+        for k in 0..32 {
+            let coder = RiceCoder::new(k);
+            for &value in values.iter() {
+                assert_eq!(rice_code_length(k, value), coder.code_length(value) as u64);
+            }
+        }
+        assert_eq!(rice_code_length(0, u32::MAX), u32::MAX as u64 + 1);
+    }
+
     #[test]
     fn test_estimator_get_k() {
         let k_values = &[0, 1, 2, 4, 5, 16];
@@ -145,6 +622,117 @@ mod test {
         assert_eq!(estimator.get_k(context), 16);
     }
 
+    #[test]
+    fn test_estimator_reset() {
+        let k_values = &[0, 1, 2, 4, 5, 16];
+        let mut estimator = KEstimator::new(400, k_values, None).with_entropy_tracking();
+        for context in [100, 255, 100] {
+            estimator.update(context, 1000);
+        }
+        estimator.reset();
+
+        let fresh = KEstimator::new(400, k_values, None);
+        assert_eq!(estimator.context_map, fresh.context_map);
+        assert_eq!(estimator.counts, fresh.counts);
+        assert!(estimator.get_statistics().is_empty());
+        assert_eq!(estimator.context_entropy(100), Some(0.0));
+
+        // The estimator can be used again after a reset.
+        estimator.update(100, 10);
+        assert_eq!(estimator.get_k(100), 4);
+        assert_eq!(estimator.get_statistics().len(), 1);
+    }
+
+    #[test]
+    fn test_sparse_estimator() {
+        let mut dense = KEstimator::default_for::<u16>();
+        let mut sparse = KEstimatorSparse::default_for::<u16>();
+        assert!(sparse.context_map.is_empty());
+
+        for i in 0..10000u32 {
+            let context = (i * 7919) % 300;
+            let encoded = (i * 104729) % (context * 3 + 1);
+            assert_eq!(sparse.get_k(context), dense.get_k(context));
+            dense.update(context, encoded);
+            sparse.update(context, encoded);
+        }
+        assert_eq!(sparse.context_map.len(), 300);
+        assert_eq!(
+            sparse.get_k(u16::MAX_CONTEXT),
+            dense.get_k(u16::MAX_CONTEXT)
+        );
+    }
+
+    #[test]
+    fn test_estimator_clamps_context() {
+        let k_values = &[0, 1, 2, 4, 5, 16];
+        let mut estimator = KEstimator::new(400, k_values, None);
+
+        estimator.update(400, 1000);
+        estimator.update(400, 1250);
+        assert_eq!(estimator.get_k(400), 16);
+        assert_eq!(estimator.get_k(401), 16);
+        assert_eq!(estimator.get_k(u32::MAX), 16);
+
+        estimator.update(u32::MAX, 0);
+        estimator.update(u32::MAX, 0);
+        assert_eq!(estimator.counts[400].0, 4);
+    }
+
+    #[test]
+    fn test_estimator_default_for() {
+        let estimator = KEstimator::default_for::<u16>();
+        assert_eq!(estimator.max_context, u16::MAX_CONTEXT);
+        assert_eq!(estimator.k_values, u16::K_VALUES);
+        assert_eq!(estimator.halve_at, u16::COUNT_SCALING);
+        assert_eq!(estimator.context_map.len(), u16::MAX_CONTEXT as usize + 1);
+    }
+
+    #[test]
+    fn test_estimator_debug() {
+        let mut estimator = KEstimator::new(1000, &[0, 1, 2], None);
+        estimator.update(12, 3);
+        estimator.update(12, 4);
+        estimator.update(900, 20);
+
+        assert_eq!(
+            format!("{:?}", estimator),
+            "KEstimator { max_context: 1000, k_values: [0, 1, 2], halve_at: None, \
+             used_contexts: 2, top_contexts: {900: ContextSummary { best_k: 2, code_length: 8 }, \
+             12: ContextSummary { best_k: 2, code_length: 7 }} }"
+        );
+
+        // Small context maps also show the contexts that were not used.
+        let estimator = KEstimator::new(200, &[0, 1, 2], None);
+        let debug = format!("{:?}", estimator);
+        assert!(debug.contains("used_contexts: 0"));
+        assert!(debug.contains("9: ContextSummary { best_k: 2, code_length: 0 }"));
+        assert!(!debug.contains("10: ContextSummary"));
+    }
+
+    #[test]
+    fn test_estimator_k_range() {
+        let mut estimator = KEstimator::new_with_k_range(400, 2, 5, Some(1024));
+        assert_eq!(estimator.k_values, [2, 3, 4, 5]);
+        assert_eq!(estimator.context_map.len(), 401);
+
+        let mut expected = KEstimator::new(400, &[2, 3, 4, 5], Some(1024));
+        for encoded in [0, 3, 70, 12, 1000, 4] {
+            estimator.update(100, encoded);
+            expected.update(100, encoded);
+        }
+        assert_eq!(estimator.context_map, expected.context_map);
+        assert_eq!(estimator.get_k(100), expected.get_k(100));
+
+        assert_eq!(KEstimator::new_with_k_range(10, 7, 7, None).k_values, [7]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_estimator_empty_k_range() {
+        KEstimator::new_with_k_range(100, 3, 2, None);
+    }
+
     #[test]
     #[should_panic]
     fn test_estimator_no_k_values() {
@@ -181,4 +769,288 @@ mod test {
         assert_eq!(ks[1], 1169);
         assert_eq!(ks[2], 588);
     }
+
+    #[test]
+    fn test_estimator_periodic_count_scaling_threshold() {
+        let mut estimator = KEstimator::new(120, &[0, 2], Some(1024));
+        let context = 43;
+
+        estimator.update(context, 4084);
+        //  k:      0     2
+        //  len:   4085  1024
+        //  total: 4085  1024 (the minimum is exactly the threshold)
+        let ks = &estimator.context_map[context as usize];
+        assert_eq!(ks[0], 4085);
+        assert_eq!(ks[1], 1024);
+
+        let mut estimator = KEstimator::new(120, &[0], Some(1024));
+
+        estimator.update(context, 1023);
+        //  k:      0
+        //  total: 1024 (the minimum is exactly the threshold)
+        assert_eq!(estimator.context_map[context as usize][0], 1024);
+
+        estimator.update(context, 0);
+        //  k:      0
+        //  total: 1025 (before scaling)
+        assert_eq!(estimator.context_map[context as usize][0], 512);
+    }
+
+    #[test]
+    fn test_estimator_periodic_count_scaling_boundary() {
+        let mut estimator = KEstimator::new(120, &[0, 1], Some(5));
+        let context = 7;
+
+        estimator.update(context, 3);
+        //  k:      0  1
+        //  len:    4  3
+        //  total:  4  3
+        assert_eq!(estimator.context_map[context as usize], [4, 3]);
+
+        estimator.update(context, 0);
+        //  k:      0  1
+        //  len:    1  2
+        //  total:  5  5 (the minimum is exactly the threshold)
+        assert_eq!(estimator.context_map[context as usize], [5, 5]);
+
+        estimator.update(context, 0);
+        //  k:      0  1
+        //  len:    1  2
+        //  total:  6  7 (before scaling)
+        assert_eq!(estimator.context_map[context as usize], [3, 3]);
+    }
+
+    // Without count scaling, the code lengths of the largest residuals of
+    // 16-bit images grow past u32::MAX.
+    #[test]
+    fn test_estimator_without_count_scaling() {
+        let k_values = &[0, 8, 16];
+        let mut estimator = KEstimator::new(0, k_values, None);
+        let mut sparse = KEstimatorSparse::new(0, k_values, None);
+        for _ in 0..70000 {
+            estimator.update(0, u16::MAX as u32);
+            sparse.update(0, u16::MAX as u32);
+        }
+        assert_eq!(
+            estimator.context_map[0],
+            [70000 * 65536, 70000 * 264, 70000 * 17]
+        );
+        assert_eq!(estimator.get_k(0), 16);
+        assert_eq!(sparse.get_k(0), 16);
+
+        let mut estimator = KEstimator::new(0, &[0, 31], None);
+        estimator.update(0, u32::MAX);
+        estimator.update(0, u32::MAX);
+        assert_eq!(estimator.context_map[0], [2 << 32, 2 * 33]);
+        assert_eq!(estimator.get_k(0), 31);
+    }
+
+    #[test]
+    fn test_estimator_context_entropy() {
+        let mut estimator = KEstimator::new(100, &[0, 1, 2], None).with_entropy_tracking();
+        let context = 20;
+        assert_eq!(estimator.context_entropy(context), Some(0.0));
+
+        // A geometric distribution with P(v) = 2^-(v+1) has an entropy of 2 bits.
+        // The value v is encoded 2^(15-v) times, so the empirical distribution
+        // is truncated at v = 15.
+        for v in 0..16u32 {
+            for _ in 0..1 << (15 - v) {
+                estimator.update(context, v);
+            }
+        }
+        let entropy = estimator.context_entropy(context).unwrap();
+        assert!((entropy - 2.0).abs() < 0.001, "{}", entropy);
+
+        // The context is clamped, and a single value has no entropy.
+        estimator.update(500, 7);
+        assert_eq!(estimator.context_entropy(100), Some(0.0));
+        assert_eq!(estimator.context_entropy(101), Some(0.0));
+        estimator.update(100, 8);
+        assert_eq!(estimator.context_entropy(100), Some(1.0));
+
+        let estimator = KEstimator::new(100, &[0, 1, 2], None);
+        assert_eq!(estimator.context_entropy(context), None);
+    }
+
+    #[test]
+    fn test_estimator_get_statistics() {
+        let mut estimator = KEstimator::new(100, &[0, 1, 2], None);
+        assert!(estimator.get_statistics().is_empty());
+
+        estimator.update(7, 0);
+        estimator.update(7, 0);
+        estimator.update(50, 0);
+        estimator.update(50, 2);
+
+        let statistics = estimator.get_statistics();
+        assert_eq!(statistics.len(), 2);
+
+        // A context with only zeros has no uncertainty, but a rice code
+        // still needs at least one bit.
+        assert_eq!(
+            statistics[0],
+            ContextStatistics {
+                context: 7,
+                best_k: 0,
+                count: 2,
+                mean: 0.0,
+                entropy: 0.0,
+                code_length: 1.0,
+            }
+        );
+
+        // With a mean of 1, the geometric distribution is P(v) = 2^-(v+1),
+        // for which unary coding (k = 0) is optimal and needs 2 bits per value.
+        let ContextStatistics {
+            context,
+            best_k,
+            count,
+            mean,
+            entropy,
+            code_length,
+        } = statistics[1];
+        assert_eq!((context, best_k, count, mean), (50, 0, 2, 1.0));
+        assert!((entropy - 2.0).abs() < 1e-9);
+        assert!((code_length - 2.0).abs() < 1e-9);
+
+        // 2^31 does not fit an i32 exponent.
+        let mut estimator = KEstimator::new_with_k_range(0, 31, 31, None);
+        estimator.update(0, u32::MAX >> 1);
+        let statistics = estimator.get_statistics();
+        assert_eq!(statistics[0].best_k, 31);
+        assert!(statistics[0].code_length.is_finite());
+        assert!(statistics[0].code_length > 32.0);
+    }
+
+    #[test]
+    fn test_optimal_k_for_mean() {
+        assert_eq!(optimal_k_for_mean(0.0), 0);
+        assert_eq!(optimal_k_for_mean(1.0), 0);
+        assert_eq!(optimal_k_for_mean(2.0), 1);
+        assert_eq!(optimal_k_for_mean(8.0), 3);
+        assert_eq!(optimal_k_for_mean(1e12), 31);
+        assert_eq!(optimal_k_for_mean(f64::NAN), 0);
+
+        // Feed the quantiles of geometric distributions to the adaptive
+        // estimator, and check that it converges to the same k.
+        for mean in [2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0] {
+            let mut estimator = KEstimator::new(0, u16::K_VALUES, None);
+            let q: f64 = mean / (1.0 + mean);
+            let count = 10000;
+            for i in 0..count {
+                let u = (i as f64 + 0.5) / count as f64;
+                estimator.update(0, (u.ln() / q.ln()).floor() as u32);
+            }
+            assert_eq!(
+                estimator.get_k(0),
+                optimal_k_for_mean(mean),
+                "mean: {}",
+                mean
+            );
+        }
+    }
+
+    #[test]
+    fn test_optimal_k_for_context() {
+        let mut estimator = KEstimator::new(u8::MAX_CONTEXT, u8::K_VALUES, None);
+        // No values were encoded in the context yet.
+        assert_eq!(estimator.optimal_k_for_context(0), 0);
+
+        // Encode the quantiles of a geometric distribution with a different
+        // mean in each context.
+        let means = [0.5, 2.0, 5.0, 10.0, 20.0, 40.0, 1000.0];
+        for (context, &mean) in means.iter().enumerate() {
+            let q: f64 = mean / (1.0 + mean);
+            let count = 10000;
+            for i in 0..count {
+                let u = (i as f64 + 0.5) / count as f64;
+                estimator.update(context as u32, (u.ln() / q.ln()).floor() as u32);
+            }
+        }
+
+        for context in 0..means.len() as u32 {
+            assert_eq!(
+                estimator.optimal_k_for_context(context),
+                estimator.get_k(context),
+                "mean: {}",
+                means[context as usize]
+            );
+        }
+        // The optimal k for a mean of 1000 is 9, the closest k value is 5.
+        assert_eq!(estimator.optimal_k_for_context(6), 5);
+        assert_eq!(estimator.optimal_k_for_context(u32::MAX), 0);
+    }
+
+    // The expected rice code length of geometric values, using the optimal k,
+    // is between the entropy of the distribution and one more bit.
+    #[test]
+    fn test_rice_code_length_entropy_bound() {
+        for p in [0.1, 0.3, 0.5, 0.7, 0.9] {
+            // P(v) = p * (1 - p)^v
+            let q: f64 = 1.0 - p;
+            let entropy = -(q * q.log2() + p * p.log2()) / p;
+            let coder = RiceCoder::new(optimal_k_for_mean(q / p));
+
+            let mut code_length = 0.0;
+            let mut probability = p;
+            for v in 0..10_000 {
+                code_length += probability * coder.code_length(v) as f64;
+                probability *= q;
+            }
+
+            assert!(code_length >= entropy - 1e-9, "p: {}", p);
+            assert!(code_length < entropy + 1.0, "p: {}", p);
+        }
+    }
+
+    #[test]
+    fn test_mean_uniform_code_length() {
+        for max_value in [0, 1, 7, 100, 1000] {
+            for k in [0, 1, 3, 5, 16] {
+                let coder = RiceCoder::new(k);
+                let total: u32 = (0..=max_value).map(|v| coder.code_length(v)).sum();
+                let expected = total as f64 / (max_value + 1) as f64;
+                let mean = mean_uniform_code_length(max_value as u64, k);
+                assert!((mean - expected).abs() < 1e-9, "{} {}", max_value, k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bayesian_estimator() {
+        let k_values = u8::K_VALUES;
+        let zero = BayesianKEstimator::new_with_prior(510, k_values, 0, Some(1024));
+        assert_eq!(
+            zero.estimator.context_map,
+            KEstimator::new(510, k_values, Some(1024)).context_map
+        );
+
+        // Small contexts start with small k values, unlike the zero-initialized
+        // estimator that always starts with the largest k.
+        let bayesian = BayesianKEstimator::new_with_prior(510, k_values, 4, Some(1024));
+        assert_eq!(bayesian.get_k(0), 0);
+        assert_eq!(bayesian.get_k(3), 1);
+        assert_eq!(bayesian.get_k(40), 4);
+        assert_eq!(zero.get_k(3), 5);
+    }
+
+    #[test]
+    fn test_bayesian_estimator_convergence() {
+        // A few values in many rare contexts, uniformly distributed in [0, C].
+        let mut zero = KEstimator::new(510, u8::K_VALUES, Some(1024));
+        let mut bayesian = BayesianKEstimator::new_with_prior(510, u8::K_VALUES, 2, Some(1024));
+        let (mut zero_bits, mut bayesian_bits) = (0, 0);
+
+        for context in 0..=510u32 {
+            for i in 0..3u32 {
+                let encoded = (context * 7919 + i * 104729) % (context + 1);
+                zero_bits += RiceCoder::new(zero.get_k(context)).code_length(encoded);
+                bayesian_bits += RiceCoder::new(bayesian.get_k(context)).code_length(encoded);
+                zero.update(context, encoded);
+                bayesian.update(context, encoded);
+            }
+        }
+        assert!(bayesian_bits < zero_bits);
+    }
 }
@@ -0,0 +1,147 @@
+use super::error::DecompressionError;
+use super::traits::CompressDecompress;
+use std::io::{self, Read, Write};
+
+/// A transformation that is applied to an image in place.
+pub type Transform<I> = fn(&mut I);
+
+/// Compresses and decompresses images of type `I`, applying user-defined
+/// transformations before compression and after decompression.
+///
+/// The pre-compress transforms are applied in the order in which they were
+/// added, and the post-decompress transforms in the reverse order, so that
+/// adding the inverse of each pre-compress transform undoes them all.
+pub struct Pipeline<I> {
+    pre_compress: Vec<Transform<I>>,
+    post_decompress: Vec<Transform<I>>,
+}
+
+impl<I> Pipeline<I>
+where
+    I: CompressDecompress + Clone,
+{
+    /// Creates a pipeline without any transforms.
+    pub fn new() -> Pipeline<I> {
+        Pipeline {
+            pre_compress: Vec::new(),
+            post_decompress: Vec::new(),
+        }
+    }
+
+    /// Adds a transform that is applied to the image before it is compressed.
+    pub fn add_pre_compress_transform(mut self, transform: Transform<I>) -> Pipeline<I> {
+        self.pre_compress.push(transform);
+        self
+    }
+
+    /// Adds a transform that is applied to the image after it is decompressed.
+    pub fn add_post_decompress_transform(mut self, transform: Transform<I>) -> Pipeline<I> {
+        self.post_decompress.push(transform);
+        self
+    }
+
+    /// Applies the pre-compress transforms to a copy of the image, and
+    /// compresses the result.
+    pub fn compress<W>(&self, image: &I, to: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        if self.pre_compress.is_empty() {
+            return image.compress(to);
+        }
+
+        let mut image = image.clone();
+        for transform in &self.pre_compress {
+            transform(&mut image);
+        }
+        image.compress(to)
+    }
+
+    /// Decompresses an image and applies the post-decompress transforms to it.
+    pub fn decompress<R>(&self, from: R) -> Result<I, DecompressionError>
+    where
+        R: Read,
+    {
+        let mut image = I::decompress(from)?;
+        for transform in self.post_decompress.iter().rev() {
+            transform(&mut image);
+        }
+        Ok(image)
+    }
+}
+
+impl<I> Default for Pipeline<I>
+where
+    I: CompressDecompress + Clone,
+{
+    fn default() -> Self {
+        Pipeline::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Pipeline;
+    use crate::compression::CompressDecompress;
+    use image::GrayImage;
+    use std::io::Cursor;
+
+    fn add_ten(image: &mut GrayImage) {
+        image
+            .pixels_mut()
+            .for_each(|p| p.0[0] = p.0[0].wrapping_add(10));
+    }
+
+    fn sub_ten(image: &mut GrayImage) {
+        image
+            .pixels_mut()
+            .for_each(|p| p.0[0] = p.0[0].wrapping_sub(10));
+    }
+
+    fn halve(image: &mut GrayImage) {
+        image.pixels_mut().for_each(|p| p.0[0] /= 2);
+    }
+
+    fn double(image: &mut GrayImage) {
+        image.pixels_mut().for_each(|p| p.0[0] *= 2);
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        let image = GrayImage::from_raw(3, 2, vec![0, 20, 40, 60, 246, 250]).unwrap();
+
+        let pipeline = Pipeline::new()
+            .add_pre_compress_transform(add_ten)
+            .add_pre_compress_transform(halve)
+            .add_post_decompress_transform(sub_ten)
+            .add_post_decompress_transform(double);
+
+        let mut compressed = Vec::new();
+        pipeline.compress(&image, &mut compressed).unwrap();
+
+        // The compressed image holds the transformed pixels.
+        let transformed: GrayImage =
+            CompressDecompress::decompress(Cursor::new(&compressed)).unwrap();
+        assert_eq!(transformed.as_raw(), &[5, 15, 25, 35, 0, 2]);
+
+        let decompressed = pipeline.decompress(Cursor::new(&compressed)).unwrap();
+        assert_eq!(decompressed.as_raw(), &[0, 20, 40, 60, 246, 250]);
+    }
+
+    #[test]
+    fn test_empty_pipeline() {
+        let image = GrayImage::from_raw(2, 2, vec![1, 2, 3, 4]).unwrap();
+        let pipeline = Pipeline::default();
+
+        let mut compressed = Vec::new();
+        pipeline.compress(&image, &mut compressed).unwrap();
+
+        let mut expected = Vec::new();
+        image.compress(&mut expected).unwrap();
+        assert_eq!(compressed, expected);
+        assert_eq!(
+            pipeline.decompress(Cursor::new(&compressed)).unwrap(),
+            image
+        );
+    }
+}
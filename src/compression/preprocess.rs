@@ -0,0 +1,211 @@
+use super::traits::Intensity;
+
+/// Checks that the channel matches its dimensions and that there is a source
+/// pixel to sample from, if the resized channel is not empty.
+fn check_dimensions<T>(channel: &[T], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) {
+    assert_eq!(
+        channel.len() as u64,
+        src_w as u64 * src_h as u64,
+        "The channel does not match its dimensions!"
+    );
+    assert!(
+        !channel.is_empty() || dst_w == 0 || dst_h == 0,
+        "Cannot resize an empty channel!"
+    );
+}
+
+/// Resizes a channel of `src_w x src_h` pixels to `dst_w x dst_h` pixels,
+/// picking the nearest source pixel for every resized pixel.
+///
+/// # Panics
+///
+/// Panics if the channel does not have `src_w * src_h` pixels, or if it is
+/// empty and the resized channel is not.
+pub fn resize_nearest_neighbor<T>(
+    channel: &[T],
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+) -> Vec<T>
+where
+    T: Intensity,
+{
+    check_dimensions(channel, src_w, src_h, dst_w, dst_h);
+
+    let mut resized = Vec::with_capacity(dst_w as usize * dst_h as usize);
+    for y in 0..dst_h as u64 {
+        let src_y = y * src_h as u64 / dst_h as u64;
+        for x in 0..dst_w as u64 {
+            let src_x = x * src_w as u64 / dst_w as u64;
+            resized.push(channel[(src_y * src_w as u64 + src_x) as usize]);
+        }
+    }
+    resized
+}
+
+/// Returns the source coordinate that the center of the resized pixel `x`
+/// maps to, clamped to the source, together with its two neighbours.
+fn sample_position(x: u32, src: u32, dst: u32) -> (usize, usize, f64) {
+    let position = (x as f64 + 0.5) * src as f64 / dst as f64 - 0.5;
+    let position = position.clamp(0.0, (src - 1) as f64);
+    let low = position.floor() as usize;
+    let high = (low + 1).min(src as usize - 1);
+    (low, high, position - low as f64)
+}
+
+/// Resizes a channel of `src_w x src_h` pixels to `dst_w x dst_h` pixels,
+/// using bilinear interpolation between the four nearest source pixels.
+///
+/// # Panics
+///
+/// Panics if the channel does not have `src_w * src_h` pixels, or if it is
+/// empty and the resized channel is not.
+pub fn resize_bilinear<T>(channel: &[T], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<T>
+where
+    T: Intensity,
+{
+    check_dimensions(channel, src_w, src_h, dst_w, dst_h);
+
+    let mut resized = Vec::with_capacity(dst_w as usize * dst_h as usize);
+    interpolate_bilinear(
+        |x, y| channel[y * src_w as usize + x].into(),
+        (src_w, src_h),
+        (dst_w, dst_h),
+        // The interpolated value is between two source pixels,
+        // so it always fits.
+        |value| resized.push(T::try_from(value).unwrap_or_default()),
+    );
+    resized
+}
+
+/// Interpolates the `dst` pixels between the four nearest `src` pixels, and
+/// passes them to `output` in raster-scan order.
+fn interpolate_bilinear<P, F>(pixel: P, src: (u32, u32), dst: (u32, u32), mut output: F)
+where
+    P: Fn(usize, usize) -> i32,
+    F: FnMut(i32),
+{
+    let pixel = |x: usize, y: usize| pixel(x, y) as f64;
+
+    for y in 0..dst.1 {
+        let (y0, y1, dy) = sample_position(y, src.1, dst.1);
+        for x in 0..dst.0 {
+            let (x0, x1, dx) = sample_position(x, src.0, dst.0);
+
+            let top = pixel(x0, y0) * (1.0 - dx) + pixel(x1, y0) * dx;
+            let bottom = pixel(x0, y1) * (1.0 - dx) + pixel(x1, y1) * dx;
+            output((top * (1.0 - dy) + bottom * dy).round() as i32);
+        }
+    }
+}
+
+/// Returns the dimensions of a chroma channel that was downsampled by
+/// `downsample_chroma`.
+pub(crate) fn chroma_dimensions(width: u32, height: u32) -> (u32, u32) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+/// Halves the width and height of a chroma channel, replacing each block of
+/// 2x2 pixels by their rounded mean. The blocks on the right and bottom edges
+/// of odd-sized channels have fewer pixels.
+///
+/// # Panics
+///
+/// Panics if the channel does not have `width * height` pixels.
+pub(crate) fn downsample_chroma(channel: &[i32], width: u32, height: u32) -> Vec<i32> {
+    check_dimensions(channel, width, height, 0, 0);
+
+    let (width, height) = (width as usize, height as usize);
+    let (dst_w, dst_h) = chroma_dimensions(width as u32, height as u32);
+
+    let mut downsampled = Vec::with_capacity(dst_w as usize * dst_h as usize);
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            let (mut sum, mut count) = (0, 0);
+            for row in y..(y + 2).min(height) {
+                for column in x..(x + 2).min(width) {
+                    sum += channel[row * width + column];
+                    count += 1;
+                }
+            }
+            downsampled.push((sum + count / 2).div_euclid(count));
+        }
+    }
+    downsampled
+}
+
+/// Upsamples a chroma channel that was downsampled by `downsample_chroma`
+/// back to `width x height` pixels, using bilinear interpolation.
+///
+/// # Panics
+///
+/// Panics if the channel does not have the downsampled dimensions.
+pub(crate) fn upsample_chroma(channel: &[i32], width: u32, height: u32) -> Vec<i32> {
+    let (src_w, src_h) = chroma_dimensions(width, height);
+    check_dimensions(channel, src_w, src_h, width, height);
+
+    let mut upsampled = Vec::with_capacity(width as usize * height as usize);
+    interpolate_bilinear(
+        |x, y| channel[y * src_w as usize + x],
+        (src_w, src_h),
+        (width, height),
+        |value| upsampled.push(value),
+    );
+    upsampled
+}
+
+#[cfg(test)]
+mod test {
+    use super::{downsample_chroma, resize_bilinear, resize_nearest_neighbor, upsample_chroma};
+
+    #[test]
+    fn test_resize_nearest_neighbor() {
+        let channel: Vec<u8> = vec![1, 2, 3, 4];
+
+        let upscaled = resize_nearest_neighbor(&channel, 2, 2, 4, 4);
+        assert_eq!(upscaled, [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+
+        assert_eq!(resize_nearest_neighbor(&upscaled, 4, 4, 2, 2), channel);
+        assert_eq!(resize_nearest_neighbor(&channel, 2, 2, 2, 2), channel);
+        assert!(resize_nearest_neighbor(&channel, 2, 2, 0, 5).is_empty());
+    }
+
+    #[test]
+    fn test_resize_bilinear() {
+        let channel: Vec<u16> = vec![0, 100];
+        assert_eq!(resize_bilinear(&channel, 2, 1, 4, 1), [0, 25, 75, 100]);
+        assert_eq!(resize_bilinear(&channel, 1, 2, 1, 4), [0, 25, 75, 100]);
+        assert_eq!(resize_bilinear(&channel, 2, 1, 1, 1), [50]);
+
+        let constant: Vec<u8> = vec![200; 15];
+        assert_eq!(resize_bilinear(&constant, 5, 3, 7, 11), vec![200; 77]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_resize_wrong_dimensions() {
+        let channel: Vec<u8> = vec![1, 2, 3];
+        resize_bilinear(&channel, 2, 2, 4, 4);
+    }
+
+    #[test]
+    fn test_chroma_subsampling() {
+        #[rustfmt::skip]
+        let channel = vec![
+            1, 3, -4, -6, 9,
+            5, 7, -8, -9, 1,
+            2, 2, 0, 0, -3,
+        ];
+        let downsampled = downsample_chroma(&channel, 5, 3);
+        assert_eq!(downsampled, [4, -7, 5, 2, 0, -3]);
+
+        let upsampled = upsample_chroma(&downsampled, 5, 3);
+        assert_eq!(upsampled.len(), 15);
+        assert_eq!(upsample_chroma(&[-12; 6], 5, 3), vec![-12; 15]);
+
+        assert!(downsample_chroma(&[], 0, 7).is_empty());
+        assert!(upsample_chroma(&[], 0, 7).is_empty());
+        assert_eq!(upsample_chroma(&[42], 1, 1), [42]);
+    }
+}
@@ -1,5 +1,6 @@
 use super::error::DecompressionError;
 use super::format::{read_header, Header, PixelDepth};
+use super::options::CodingOptions;
 use std::io::{self, Read, Write};
 
 /// This trait is implemented by all types that can
@@ -22,6 +23,23 @@ pub trait Intensity: Into<i32> + TryFrom<i32> + Default + Clone + Copy {
     const PIXEL_DEPTH: PixelDepth;
 }
 
+/// Returns whether the values are sorted in strictly increasing order, which
+/// also means that there are no duplicates.
+const fn is_sorted_and_unique(values: &[u8]) -> bool {
+    let mut i = 1;
+    while i < values.len() {
+        if values[i - 1] >= values[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// Every k value must be tracked by exactly one slot of the `KEstimator`.
+const _: () = assert!(is_sorted_and_unique(u8::K_VALUES));
+const _: () = assert!(is_sorted_and_unique(u16::K_VALUES));
+
 impl Intensity for u8 {
     const K_VALUES: &'static [u8] = &[0, 1, 2, 3, 4, 5];
 
@@ -45,10 +63,28 @@ impl Intensity for u16 {
 /// This trait is implemented by all image types that are supported by the felics
 /// compression algorithm.
 pub trait CompressDecompress {
+    /// Compresses the image using the default coding options.
     fn compress<W>(&self, to: W) -> io::Result<()>
     where
         W: Write;
 
+    /// Compresses the image using the given coding options.
+    fn compress_with_options<W>(&self, to: W, options: CodingOptions) -> io::Result<()>
+    where
+        W: Write;
+
+    /// Compresses the image without writing the header, using the coding
+    /// options of the given header, which must describe this image.
+    ///
+    /// This is the counterpart of `decompress_with_header`: the header can be
+    /// written separately using `write_header`.
+    fn compress_with_header<W>(&self, to: W, header: &Header) -> io::Result<()>
+    where
+        W: Write;
+
+    /// Decompresses an image whose header was already read using
+    /// `read_header`, so that the header can be inspected before deciding
+    /// whether to decompress the image.
     fn decompress_with_header<R>(from: R, header: &Header) -> Result<Self, DecompressionError>
     where
         Self: Sized,
@@ -63,3 +99,17 @@ pub trait CompressDecompress {
         Self::decompress_with_header(from, &header)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::is_sorted_and_unique;
+
+    #[test]
+    fn test_is_sorted_and_unique() {
+        assert!(is_sorted_and_unique(&[]));
+        assert!(is_sorted_and_unique(&[3]));
+        assert!(is_sorted_and_unique(&[0, 1, 5, 9]));
+        assert!(!is_sorted_and_unique(&[0, 1, 1, 2]));
+        assert!(!is_sorted_and_unique(&[2, 1]));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod coding;
 pub mod compression;
+pub mod tiff_stack;
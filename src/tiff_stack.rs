@@ -0,0 +1,146 @@
+//! Compression of multi-page TIFF files, like the stacks of grayscale slices
+//! used in microscopy and medical imaging.
+//!
+//! A stack is stored as the signature `FLST`, the number of frames as a
+//! big-endian `u32`, and then every frame as a complete felics image.
+
+use crate::compression::{decompress_image, CompressDecompress, DecompressionError};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use image::{DynamicImage, ImageBuffer, Luma};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::{ColorType, TiffError};
+
+fn tiff_error(err: TiffError) -> io::Error {
+    match err {
+        TiffError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Compresses all the frames of the multi-page TIFF file at `path`.
+///
+/// The frames must be 8-bit or 16-bit grayscale images.
+pub fn compress_tiff_stack<W>(path: &Path, mut to: W) -> io::Result<()>
+where
+    W: Write,
+{
+    let file = BufReader::new(File::open(path)?);
+    let mut decoder = Decoder::new(file).map_err(tiff_error)?;
+
+    let mut frames = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        let colortype = decoder.colortype().map_err(tiff_error)?;
+        let frame = match (colortype, decoder.read_image().map_err(tiff_error)?) {
+            (ColorType::Gray(8), DecodingResult::U8(buf)) => {
+                ImageBuffer::<Luma<u8>, _>::from_raw(width, height, buf).map(DynamicImage::from)
+            }
+            (ColorType::Gray(16), DecodingResult::U16(buf)) => {
+                ImageBuffer::<Luma<u16>, _>::from_raw(width, height, buf).map(DynamicImage::from)
+            }
+            _ => None,
+        };
+        let frame = frame.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Only 8-bit and 16-bit grayscale frames are supported",
+            )
+        })?;
+        frames.push(frame);
+
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image().map_err(tiff_error)?;
+    }
+
+    to.write_all(b"FLST")?;
+    let num_frames: u32 = frames
+        .len()
+        .try_into()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many frames"))?;
+    to.write_u32::<BigEndian>(num_frames)?;
+    for frame in frames {
+        match frame {
+            DynamicImage::ImageLuma8(frame) => frame.compress(&mut to)?,
+            DynamicImage::ImageLuma16(frame) => frame.compress(&mut to)?,
+            _ => unreachable!(),
+        }
+    }
+    Ok(())
+}
+
+/// Decompresses a stack compressed by `compress_tiff_stack`, and saves every
+/// frame as a TIFF file in `output_dir`, named after its index: `0000.tiff`,
+/// `0001.tiff`, ...
+///
+/// Returns the number of frames.
+pub fn decompress_tiff_stack<R>(mut from: R, output_dir: &Path) -> Result<u32, DecompressionError>
+where
+    R: Read,
+{
+    let mut magic = [0; 4];
+    from.read_exact(&mut magic)?;
+    if &magic != b"FLST" {
+        return Err(DecompressionError::InvalidSignature);
+    }
+
+    let num_frames = from.read_u32::<BigEndian>()?;
+    for index in 0..num_frames {
+        let frame = decompress_image(&mut from)?;
+        let path = output_dir.join(format!("{:04}.tiff", index));
+        frame.save(path).map_err(io::Error::other)?;
+    }
+    Ok(num_frames)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compress_tiff_stack, decompress_tiff_stack};
+    use image::GrayImage;
+    use std::fs::{self, File};
+    use std::io::Cursor;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    #[test]
+    fn test_tiff_stack() {
+        let dir = std::env::temp_dir().join(format!("felics-tiff-stack-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let frames: Vec<Vec<u8>> = (0..3u8)
+            .map(|frame| {
+                (0..20 * 10)
+                    .map(|i| (i as u8).wrapping_mul(frame))
+                    .collect()
+            })
+            .collect();
+
+        let stack_path = dir.join("stack.tiff");
+        let mut encoder = TiffEncoder::new(File::create(&stack_path).unwrap()).unwrap();
+        for frame in &frames {
+            encoder
+                .write_image::<colortype::Gray8>(20, 10, frame)
+                .unwrap();
+        }
+        drop(encoder);
+
+        let mut compressed = Vec::new();
+        compress_tiff_stack(&stack_path, &mut compressed).unwrap();
+
+        let output_dir = dir.join("frames");
+        fs::create_dir_all(&output_dir).unwrap();
+        let num_frames = decompress_tiff_stack(Cursor::new(compressed), &output_dir).unwrap();
+        assert_eq!(num_frames, 3);
+
+        for (index, frame) in frames.into_iter().enumerate() {
+            let path = output_dir.join(format!("{:04}.tiff", index));
+            let decompressed = image::open(path).unwrap().into_luma8();
+            assert_eq!(decompressed, GrayImage::from_raw(20, 10, frame).unwrap());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
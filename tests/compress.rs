@@ -1,11 +1,24 @@
 use felics::compression::CompressDecompress;
 use image::{self, DynamicImage};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::Instant;
 
+/// The compressed size of every image in the test suite, one
+/// `<path relative to image-suite> <size>` entry per line.
+const BASELINE: &str = include_str!("compressed-sizes.txt");
+
+/// The compression ratio of every synthetic image generated by `gen_fixtures`,
+/// one `<file name> <ratio>` entry per line, as printed by `gen_fixtures`.
+const SYNTHETIC_BASELINE: &str = include_str!("synthetic-ratios.txt");
+
+/// The maximum allowed growth of a compressed image over its baseline size.
+const MAX_SIZE_REGRESSION: f64 = 1.05;
+
 struct BenchmarkMetrics {
     compress_tm: f64,
     decompress_tm: f64,
@@ -36,7 +49,7 @@ where
 
 fn compress_file(path: &PathBuf) -> BenchmarkMetrics {
     let file_name = path.file_name().unwrap().to_str().unwrap();
-    let image = image::open(&path).unwrap();
+    let image = image::open(path).unwrap();
 
     let (width, height) = (image.width(), image.height());
     let name;
@@ -68,18 +81,33 @@ fn compress_file(path: &PathBuf) -> BenchmarkMetrics {
     results
 }
 
-/// Compress all the images in the test suite and report
-/// various metrics.
+/// Parses a baseline table of `<path> <value>` entries.
+fn parse_baseline<T>(table: &'static str) -> HashMap<&'static str, T>
+where
+    T: std::str::FromStr,
+    T::Err: Debug,
+{
+    table
+        .lines()
+        .map(|line| {
+            let (path, value) = line.rsplit_once(' ').unwrap();
+            (path, value.parse().unwrap())
+        })
+        .collect()
+}
+
+/// Compress all the images in the test suite, check that they are
+/// decompressed losslessly and that their compressed size did not
+/// regress, and report various metrics.
 #[test]
 fn compress_suite() {
-    let folders = vec![
-        format!("{}/image-suite/grayscale/8bit", env!("CARGO_MANIFEST_DIR")),
-        format!("{}/image-suite/grayscale/16bit", env!("CARGO_MANIFEST_DIR")),
-        format!("{}/image-suite/rgb/8bit", env!("CARGO_MANIFEST_DIR")),
-    ];
+    let suite = Path::new(env!("CARGO_MANIFEST_DIR")).join("image-suite");
+    let folders = vec!["grayscale/8bit", "grayscale/16bit", "rgb/8bit"];
+    let baseline: HashMap<_, usize> = parse_baseline(BASELINE);
 
     for folder in folders {
-        println!("Entering folder: {}", folder);
+        let folder = suite.join(folder);
+        println!("Entering folder: {}", folder.display());
         let files = fs::read_dir(folder).unwrap();
 
         let mut total_compress_tm = 0.0;
@@ -90,6 +118,18 @@ fn compress_suite() {
             let entry_path = file.unwrap().path();
             let metrics = compress_file(&entry_path);
 
+            let relative_path = entry_path.strip_prefix(&suite).unwrap().to_str().unwrap();
+            let baseline_size = *baseline
+                .get(relative_path)
+                .unwrap_or_else(|| panic!("No baseline size for {}", relative_path));
+            assert!(
+                metrics.compress_size as f64 <= baseline_size as f64 * MAX_SIZE_REGRESSION,
+                "{} compressed to {} bytes, the baseline is {} bytes",
+                relative_path,
+                metrics.compress_size,
+                baseline_size
+            );
+
             total_compress_tm += metrics.compress_tm;
             total_decompress_tm += metrics.decompress_tm;
             total_size += metrics.compress_size;
@@ -101,3 +141,55 @@ fn compress_suite() {
         );
     }
 }
+
+/// Generates the synthetic images in the given folder, like `make fixtures`.
+fn generate_fixtures(folder: &Path) {
+    for depth in ["8", "16"] {
+        let status = Command::new(env!("CARGO_BIN_EXE_gen_fixtures"))
+            .args(["--depth", depth, "--output"])
+            .arg(folder)
+            .stdout(Stdio::null())
+            .status()
+            .expect("Cannot run gen_fixtures");
+        assert!(status.success(), "gen_fixtures failed for depth {}", depth);
+    }
+}
+
+/// Compress the synthetic images generated by `gen_fixtures`, and check that
+/// their compression ratio did not regress from the one in the baseline.
+#[test]
+fn compress_synthetic() {
+    let folder = Path::new(env!("CARGO_TARGET_TMPDIR")).join("synthetic");
+    generate_fixtures(&folder);
+    let baseline: HashMap<_, f64> = parse_baseline(SYNTHETIC_BASELINE);
+
+    let mut images = 0;
+    for file in fs::read_dir(&folder).unwrap() {
+        let entry_path = file.unwrap().path();
+        if entry_path.extension() != Some("png".as_ref()) {
+            continue;
+        }
+        images += 1;
+
+        let raw_size = image::open(&entry_path).unwrap().as_bytes().len();
+        let metrics = compress_file(&entry_path);
+        let ratio = metrics.compress_size as f64 / raw_size as f64;
+
+        let file_name = entry_path.file_name().unwrap().to_str().unwrap();
+        let expected_ratio = *baseline
+            .get(file_name)
+            .unwrap_or_else(|| panic!("No baseline ratio for {}", file_name));
+        assert!(
+            ratio <= expected_ratio * MAX_SIZE_REGRESSION,
+            "{} has a compression ratio of {}, expected {}",
+            file_name,
+            ratio,
+            expected_ratio
+        );
+    }
+    assert_eq!(
+        images,
+        baseline.len(),
+        "gen_fixtures did not generate every image of the baseline"
+    );
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "derive")]
+
+use felics::compression::{CodingOptions, Intensity, KEstimator, PixelDepth};
+
+/// A 10-bit pixel intensity.
+#[derive(Clone, Copy, Default, Intensity)]
+#[intensity(k_values = [0, 1, 2, 3, 4, 5, 6, 7], max_context = 2046, count_scaling = 512)]
+struct U10(u16);
+
+impl From<U10> for i32 {
+    fn from(value: U10) -> i32 {
+        value.0 as i32
+    }
+}
+
+impl TryFrom<i32> for U10 {
+    type Error = ();
+
+    fn try_from(value: i32) -> Result<U10, ()> {
+        match value {
+            0..=1023 => Ok(U10(value as u16)),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Intensity)]
+#[intensity(k_values = [0, 1, 2], max_context = 510, pixel_depth = 8)]
+struct Small(u8);
+
+impl From<Small> for i32 {
+    fn from(value: Small) -> i32 {
+        value.0 as i32
+    }
+}
+
+impl TryFrom<i32> for Small {
+    type Error = ();
+
+    fn try_from(value: i32) -> Result<Small, ()> {
+        u8::try_from(value).map(Small).map_err(|_| ())
+    }
+}
+
+#[test]
+fn derive_intensity() {
+    assert_eq!(U10::K_VALUES, &[0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(U10::MAX_CONTEXT, 2046);
+    assert_eq!(U10::COUNT_SCALING, Some(512));
+    assert_eq!(U10::PIXEL_DEPTH, PixelDepth::Sixteen);
+
+    assert_eq!(Small::K_VALUES, &[0, 1, 2]);
+    assert_eq!(Small::MAX_CONTEXT, 510);
+    assert_eq!(Small::COUNT_SCALING, None);
+    assert_eq!(Small::PIXEL_DEPTH, PixelDepth::Eight);
+
+    let mut estimator = KEstimator::default_for::<U10>();
+    estimator.update(2046, 1000);
+    assert_eq!(estimator.get_k(2046), 7);
+    CodingOptions::default_for::<U10>();
+}